@@ -8,9 +8,11 @@ use std::thread::sleep;
 /// 自动完成读取器
 /// - interface 读取接口，见linefeed库
 /// - completer 自动完成器
+/// - prompt 当前的提示符
 pub struct AutoCompleteReader {
     interface: Interface<DefaultTerminal>,
     completer: Arc<ShellCompleter>,
+    prompt: String,
 }
 
 impl AutoCompleteReader {
@@ -43,6 +45,10 @@ impl AutoCompleteReader {
     }
     pub fn set_prompt(&mut self, p: &str) {
         self.interface.set_prompt(p).expect("set prompt failed");
+        self.prompt = p.to_owned();
+    }
+    pub fn prompt(&self) -> &str {
+        &self.prompt
     }
 }
 
@@ -67,6 +73,7 @@ impl AutoCompleteReader {
         let mut ret = Box::<AutoCompleteReader>::new(AutoCompleteReader {
             interface: Interface::new("ushell-rust").expect("create interface failed"),
             completer: ShellCompleter::new(),
+            prompt: String::new(),
         });
 
         ret.set_prompt(">> ");
@@ -1,18 +1,39 @@
-use crate::{autocomplete_reader::AutoCompleteReader, sys::get_process_list};
+use crate::{
+    autocomplete_reader::AutoCompleteReader,
+    connection::connect,
+    discovery::ShellProcess,
+    filter::ProcessFilter,
+    tools::format_uptime,
+};
 use shell_core::*;
 use std::{
+    io::Write,
+    net::Shutdown,
     os::unix::net::UnixStream,
-    sync::{Arc, Mutex},
-    thread::{sleep, spawn, JoinHandle},
-    time::Duration,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{spawn, JoinHandle},
 };
 
-
 pub struct Client {
     cmd_channel: Option<UnixStream>,
-    output_channel: Option<UnixStream>,
+    /// 命令的执行结果，由输出线程转发
+    replies: Option<Receiver<Message>>,
+    /// 下一条命令的请求 id
+    next_id: u64,
+    /// 已连接进程的握手信息
+    server: Option<Hello>,
     copy_stdout: Option<JoinHandle<()>>,
-    reader: Arc<Mutex<Box<AutoCompleteReader>>>,
+    /// 交互模式下的读取器，非交互模式下为 `None`
+    reader: Option<Arc<Mutex<Box<AutoCompleteReader>>>>,
+    /// 返回值的显示格式，text 或 json
+    format: String,
+    /// 最后一条命令的执行状态，0 表示成功
+    last_status: i32,
+    /// 进程执行命令时是否输出开始和结束标记，不输出时由客户端显示返回值
+    banner: bool,
 }
 
 static DEFAULT_PS1: &str = "\x1B[33m>> \x1B[0m";
@@ -24,97 +45,317 @@ impl Default for Client {
 }
 
 impl Client {
+    /// 创建一个交互式的客户端，需要在终端中运行。
     pub fn new() -> Client {
+        let reader = AutoCompleteReader::new().unwrap();
+        {
+            let mut r = reader.lock().expect("lock reader failed");
+            r.set_prompt(DEFAULT_PS1);
+            r.set_debug_command_complete_data(vec![
+                ("exit".to_owned(), "exit".to_owned()),
+                ("attach".to_owned(), "attach".to_owned()),
+                ("detach".to_owned(), "detach".to_owned()),
+                ("list".to_owned(), "list".to_owned()),
+                ("format".to_owned(), "format".to_owned()),
+            ]);
+        }
+        Client::with_reader(Some(reader))
+    }
+
+    /// 创建一个非交互式的客户端，通过 [`Client::run_script`] 执行命令，
+    /// 不需要终端，命令的错误输出和错误信息输出到标准错误。
+    pub fn non_interactive() -> Client {
+        Client::with_reader(None)
+    }
+
+    fn with_reader(reader: Option<Arc<Mutex<Box<AutoCompleteReader>>>>) -> Client {
         Client {
             cmd_channel: None,
-            output_channel: None,
+            replies: None,
+            next_id: 1,
+            server: None,
             copy_stdout: None,
-            reader: AutoCompleteReader::new().unwrap(),
+            reader,
+            format: "text".to_owned(),
+            last_status: 0,
+            banner: true,
+        }
+    }
+
+    /// 是否为交互模式
+    fn interactive(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    /// 更新交互模式下的读取器，非交互模式下不做任何事情。
+    fn update_reader(&self, f: impl FnOnce(&mut AutoCompleteReader)) -> Result<(), String> {
+        if let Some(reader) = &self.reader {
+            let mut reader = reader.lock().map_err(|err| err.to_string())?;
+            f(&mut reader);
         }
+        Ok(())
     }
 
-    fn find_process(&self, arg: &Argument) -> Vec<(String, u64)> {
-        let result: Vec<(String, u64)> = get_process_list()
+    /// 在可以连接的服务端实例中查找满足 `filter` 的实例，有多个时在交互模式下由用户选择。
+    fn find_process(&self, filter: &ProcessFilter) -> Result<ShellProcess, String> {
+        let mut processes: Vec<ShellProcess> = ShellProcess::list()
             .into_iter()
-            .map(|(pid, name)| (name, pid.parse().unwrap()))
+            .filter(|process| filter.matches(process))
             .collect();
-
-        match arg {
-            Argument::Str(name) => result
-                .into_iter()
-                .filter(|(name_, _)| name_.starts_with(name))
-                .collect::<Vec<(String, u64)>>(),
-            Argument::Int(pid) => result
-                .into_iter()
-                .filter(|(_, pid_)| pid.to_owned() as u64 == *pid_)
-                .collect(),
+        match processes.len() {
+            0 => {
+                // 指定了进程 id 时给出进程没有侦听或已经失效的具体原因
+                if let Some(pid) = filter.pid {
+                    DiscoveryRecord::resolve(pid, filter.instance.as_deref())?;
+                }
+                Err("process not found".to_string())
+            }
+            1 => Ok(processes.remove(0)),
+            _ if !self.interactive() => Err(format!(
+                "multiple process found, narrow it down with filters such as pid=, user= or instance=:\n{}",
+                processes
+                    .iter()
+                    .map(Client::describe_process)
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )),
+            _ => self.select_process(processes),
         }
     }
 
-    fn make_uds_path(pid: &u64) -> (String, String) {
-        (
-            format!("/tmp/rust_shell_cmd_{}", pid),
-            format!("/tmp/rust_shell_output_{}", pid),
+    /// 进程的描述，用于选择菜单和错误信息
+    fn describe_process(process: &ShellProcess) -> String {
+        format!(
+            "{} {} [{}] {}",
+            process.pid,
+            process.name,
+            process.instance,
+            process
+                .process
+                .as_ref()
+                .map(|process| format!("{}  {}", process.user, process.command()))
+                .unwrap_or_default()
         )
     }
 
-    fn parse_auto_complete(line: &str) -> Vec<String> {
-        line.split_whitespace().map(|s| s.to_owned()).collect()
+    /// 显示编号的进程列表，由用户输入编号选择一个进程，直接回车取消。
+    fn select_process(&self, mut processes: Vec<ShellProcess>) -> Result<ShellProcess, String> {
+        for (index, process) in processes.iter().enumerate() {
+            println!("{:>3}) {}", index + 1, Client::describe_process(process));
+        }
+        let reader = self
+            .reader
+            .as_ref()
+            .ok_or("interactive mode requires a terminal".to_owned())?;
+        let mut reader = reader.lock().map_err(|err| err.to_string())?;
+        let prompt = reader.prompt().to_owned();
+        reader.set_prompt(&format!("select [1-{}]: ", processes.len()));
+        let answer = reader.read();
+        reader.set_prompt(&prompt);
+        let answer = answer?;
+        if answer.trim().is_empty() {
+            return Err("attach cancelled".to_owned());
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(index) if (1..=processes.len()).contains(&index) => {
+                Ok(processes.swap_remove(index - 1))
+            }
+            _ => Err(format!("invalid selection: {}", answer.trim())),
+        }
     }
 
-    fn attach_process(&mut self, args: &Vec<Argument>) -> Result<(), String> {
-        if args.len() != 1 {
-            return Err("argument number error".to_string());
+    /// 将命令的元信息转换为自动完成数据，显示内容为命令签名和说明。
+    fn parse_auto_complete(commands: Vec<CommandMeta>) -> Vec<(String, String)> {
+        commands
+            .into_iter()
+            .map(|meta| {
+                let display = match meta.summary() {
+                    "" => meta.signature(),
+                    summary => format!("{}  {}", meta.signature(), summary),
+                };
+                (meta.name, display)
+            })
+            .collect()
+    }
+
+    /// 连接到满足过滤条件的进程，过滤条件见 [`ProcessFilter`]。
+    fn attach_process(&mut self, args: &[NamedArgument]) -> Result<(), String> {
+        let process = self.find_process(&ProcessFilter::parse(args)?)?;
+        let cmd_channel = connect(&process.socket)?;
+        let mut reader =
+            MessageReader::new(cmd_channel.try_clone().map_err(|err| err.to_string())?);
+        let server = Client::handshake(&cmd_channel, &mut reader)?;
+        let commands = match reader.read()? {
+            Message::CommandList(commands) => commands,
+            Message::Error { message, .. } => return Err(message),
+            other => return Err(format!("unexpected message: {:?}", other)),
+        };
+        self.update_reader(|reader| {
+            reader.append_debug_command_complete_data(Client::parse_auto_complete(commands))
+        })?;
+
+        // 命令的输出和执行结果在同一个连接上返回，由输出线程读取，执行结果转发给等待的命令
+        let (sender, receiver) = channel();
+        self.close_connection();
+        self.cmd_channel = Some(cmd_channel);
+        self.server = Some(server);
+        self.replies = Some(receiver);
+        let interactive = self.interactive();
+        self.copy_stdout = Some(spawn(move || {
+            Client::copy_output(reader, sender, interactive)
+        }));
+
+        self.update_reader(|reader| {
+            reader.set_prompt(format!("\x1B[32m{} >> \x1B[0m", process.name).as_str())
+        })?;
+
+        if self.format != "text" {
+            if let Err(err) = self.send_format() {
+                eprintln!("Warning: {}, use text format", err);
+            }
         }
 
-        let pids = self.find_process(&args[0]);
-        if pids.is_empty() {
-            return Err("process not found".to_string());
+        // 非交互模式下只输出返回值，便于其他程序处理
+        self.banner = true;
+        if !interactive
+            && self
+                .server
+                .as_ref()
+                .is_some_and(|server| server.supports(Capability::Banner))
+        {
+            self.run_custom_command(".banner off")?;
+            self.banner = self.last_status != 0;
         }
-        if pids.len() != 1 {
-            return Err(format!("multiple process found: \n{}", {
-                pids.iter()
-                    .map(|(comm, pid)| format!("{}: {}", comm, pid))
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            }));
+
+        Ok(())
+    }
+
+    /// 与进程交换握手信息，返回进程的握手信息。
+    ///
+    /// 协议版本不兼容时拒绝连接，版本不同但协议兼容时给出提示。
+    fn handshake(
+        mut conn: &UnixStream,
+        reader: &mut MessageReader<UnixStream>,
+    ) -> Result<Hello, String> {
+        let hello = Hello::new(
+            concat!("shell_client ", env!("CARGO_PKG_VERSION")),
+            &[Capability::Json, Capability::Streaming],
+        );
+        write_message(&mut conn, &Message::Hello(hello.clone()))?;
+        let server = match reader.read()? {
+            Message::Hello(server) => server,
+            // 不支持握手的旧版本进程首先发送的是命令列表
+            Message::CommandList(_) => {
+                return Err("process does not support protocol negotiation, \
+                    please upgrade its shell_server"
+                    .to_owned())
+            }
+            other => return Err(format!("unexpected message: {:?}", other)),
+        };
+        hello.check(&server)?;
+        if server.core_version != hello.core_version {
+            eprintln!(
+                "\x1B[90mNote: {} ({}) uses shell_core {}, local shell_core is {}\x1B[0m",
+                server.process_name, server.version, server.core_version, hello.core_version
+            );
         }
+        Ok(server)
+    }
 
-        let (cmd_path, output_path) = Self::make_uds_path(&pids[0].1);
-        self.cmd_channel = Some(UnixStream::connect(&cmd_path).map_err(|err| err.to_string())?);
-        self.output_channel =
-            Some(UnixStream::connect(&output_path).map_err(|err| err.to_string())?);
+    /// 显示命令的输出，其他消息转发给等待执行结果的命令，直到连接关闭。
+    ///
+    /// 交互模式下错误输出以红色显示，非交互模式下错误输出到标准错误。
+    fn copy_output(
+        mut reader: MessageReader<UnixStream>,
+        replies: Sender<Message>,
+        interactive: bool,
+    ) {
+        while let Ok(message) = reader.read() {
+            let mut stdout = std::io::stdout();
+            let _ = match message {
+                Message::Output {
+                    stream: Stream::Stderr,
+                    data,
+                    ..
+                } if !interactive => {
+                    let mut stderr = std::io::stderr();
+                    stderr.write_all(&data).and_then(|_| stderr.flush())
+                }
+                Message::Output {
+                    stream: Stream::Stdout,
+                    data,
+                    ..
+                } => stdout.write_all(&data),
+                Message::Output {
+                    stream: Stream::Stderr,
+                    data,
+                    ..
+                } => stdout
+                    .write_all(b"\x1B[31m")
+                    .and_then(|_| stdout.write_all(&data))
+                    .and_then(|_| stdout.write_all(b"\x1B[0m")),
+                other => {
+                    if replies.send(other).is_err() {
+                        break;
+                    }
+                    Ok(())
+                }
+            };
+            let _ = stdout.flush();
+        }
+    }
 
-        if let Some(c) = &self.cmd_channel {
-            self.reader
-                .lock()
-                .map_err(|err| err.to_string())?
-                .append_debug_command_complete_data(
-                    Client::parse_auto_complete(&read_line(
-                        &mut c.try_clone().map_err(|err| err.to_string())?,
-                    )?)
-                    .into_iter()
-                    .map(|x| (x.clone(), x.clone()))
-                    .collect(),
-                )
-        }
-
-        let mut output_channel_copy = self
-            .output_channel
-            .as_ref()
-            .ok_or("not attach to process")?
-            .try_clone()
-            .map_err(|err| err.to_string())?;
+    fn send_format(&mut self) -> Result<(), String> {
+        if self.format == "json"
+            && !self
+                .server
+                .as_ref()
+                .is_some_and(|server| server.supports(Capability::Json))
+        {
+            return Err("process does not support json format".to_owned());
+        }
+        let directive = format!(".format {}", self.format);
+        self.run_custom_command(&directive)
+    }
 
-        self.copy_stdout = Some(spawn(move || {
-            let _ = std::io::copy(&mut output_channel_copy, &mut std::io::stdout());
-        }));
+    fn set_format(&mut self, args: &[Argument]) -> Result<(), String> {
+        if args.len() != 1 {
+            return Err("argument number error".to_string());
+        }
+
+        let format = args[0].to_string();
+        if format != "text" && format != "json" {
+            return Err(format!("unknown format: {}", format));
+        }
+        self.format = format;
 
-        self.reader
-            .lock()
-            .map_err(|err| err.to_string())?
-            .set_prompt(format!("\x1B[32m{} >> \x1B[0m", pids[0].0).as_str());
+        if self.cmd_channel.is_some() {
+            self.send_format()?;
+        }
+        Ok(())
+    }
 
+    /// 列出可以连接的进程
+    fn list_processes(&self) -> Result<(), String> {
+        let processes = ShellProcess::list();
+        if processes.is_empty() {
+            println!("no process serves shell commands");
+            return Ok(());
+        }
+        println!(
+            "{:<8} {:<16} {:<12} {:<24} UPTIME",
+            "PID", "NAME", "INSTANCE", "VERSION"
+        );
+        for process in processes {
+            println!(
+                "{:<8} {:<16} {:<12} {:<24} {}",
+                process.pid,
+                process.name,
+                process.instance,
+                process.version,
+                format_uptime(process.uptime())
+            );
+        }
         Ok(())
     }
 
@@ -122,86 +363,182 @@ impl Client {
         Err("exit".to_owned())
     }
 
+    /// 向进程发送一条命令，并等待命令执行完成。
+    ///
+    /// 返回的错误表示与进程的连接出现问题，命令本身执行失败时会打印错误信息并记录在
+    /// [`Client::last_status`] 中。
+    pub fn run_custom_command(&mut self, line: &str) -> Result<(), String> {
+        let cmd_channel = self
+            .cmd_channel
+            .as_mut()
+            .ok_or("not attach to process".to_owned())?;
+        let replies = self
+            .replies
+            .as_ref()
+            .ok_or("not attach to process".to_owned())?;
 
+        let id = self.next_id;
+        self.next_id += 1;
+        write_message(
+            cmd_channel,
+            &Message::Invoke {
+                id,
+                line: line.to_owned(),
+            },
+        )?;
 
-    pub fn run_custom_command(&mut self, line: &String) -> Result<(), String> {
-        match self.cmd_channel {
-            None => Err("not attach to process".to_owned()),
-            Some(ref mut cmd_channel) => write_line(cmd_channel, line),
+        loop {
+            match replies.recv().map_err(|_| "connection closed".to_owned())? {
+                Message::Result {
+                    id: reply_id,
+                    value,
+                    duration,
+                } if reply_id == id => {
+                    self.last_status = 0;
+                    if !self.banner && !line.starts_with('.') && !value.is_empty() {
+                        println!("{}", value);
+                    }
+                    // 指令的执行时间没有意义，不显示，非交互模式下也不显示
+                    if !line.starts_with('.') && self.interactive() {
+                        println!("\x1B[90m({:?})\x1B[0m", duration);
+                    }
+                }
+                Message::Error {
+                    id: reply_id,
+                    message,
+                    duration,
+                } if reply_id == id || reply_id == 0 => {
+                    self.last_status = 1;
+                    if self.interactive() {
+                        println!(
+                            "\x1B[31mError: {}\x1B[0m \x1B[90m({:?})\x1B[0m",
+                            message.trim(),
+                            duration
+                        );
+                    } else {
+                        eprintln!("Error: {}", message.trim());
+                    }
+                }
+                // 心跳以及之前被放弃等待的命令的结果
+                _ => continue,
+            }
+            return Ok(());
         }
     }
 
-    pub fn detach_process(&mut self) {
-        self.cmd_channel = None;
-        self.output_channel = None;
-        self.copy_stdout = None;
-        self.reader
-            .lock()
-            .expect("lock reader failed")
-            .set_prompt(DEFAULT_PS1);
+    /// 最后一条命令的执行状态，0 表示成功，1 表示失败。
+    pub fn last_status(&self) -> i32 {
+        self.last_status
     }
 
+    /// 关闭与进程的连接，并等待输出线程退出。
+    ///
+    /// 输出线程持有连接的副本，只丢弃连接不会关闭套接字，需要先关闭连接使输出线程读到连接结束。
+    fn close_connection(&mut self) {
+        if let Some(cmd_channel) = self.cmd_channel.take() {
+            let _ = cmd_channel.shutdown(Shutdown::Both);
+        }
+        self.replies = None;
+        self.server = None;
+        if let Some(copy_stdout) = self.copy_stdout.take() {
+            let _ = copy_stdout.join();
+        }
+    }
 
-    fn init_reader(&mut self) -> Result<(), String> {
-        let mut r = self.reader.lock().map_err(|err| err.to_string())?;
-        r.set_prompt(DEFAULT_PS1);
-        r.set_debug_command_complete_data(vec![
-            ("exit".to_owned(), "exit".to_owned()),
-            ("attach".to_owned(), "attach".to_owned()),
-            ("detach".to_owned(), "detach".to_owned()),
-        ]);
-
-        Ok(())
+    pub fn detach_process(&mut self) {
+        self.close_connection();
+        self.update_reader(|reader| reader.set_prompt(DEFAULT_PS1))
+            .expect("lock reader failed");
     }
 
+    /// 连接到进程，`target` 为进程 id 或进程名称的前缀，`instance` 为进程中服务端实例的名称。
+    pub fn attach(&mut self, target: Argument, instance: Option<&str>) -> Result<(), String> {
+        let mut args = vec![NamedArgument {
+            name: None,
+            value: target,
+        }];
+        if let Some(name) = instance {
+            args.push(NamedArgument {
+                name: Some("instance".to_owned()),
+                value: Argument::Str(name.to_owned()),
+            });
+        }
+        self.attach_process(&args)
+    }
 
-    fn run_builtin_command(&mut self, cmd: &String, args: &Vec<Argument>) -> Result<(), String> {
-        match cmd.as_str() {
-            "attach" => self.attach_process(args),
+    fn run_builtin_command(&mut self, cmd: &str, args: &str) -> Result<(), String> {
+        match cmd {
+            "attach" => self.attach_process(&parse_named_arguments(args)),
             "detach" => {
                 self.detach_process();
                 Ok(())
             }
+            "format" => self.set_format(&parse_arguments(args)),
+            "list" => self.list_processes(),
             "exit" => Self::exit(),
             _ => Err("custom".to_owned()),
         }
     }
 
+    /// 执行一行输入，内置命令在客户端执行，其他命令发送给进程。
+    ///
+    /// 返回 `Err("exit")` 表示需要退出。
+    fn execute_line(&mut self, line: &str) -> Result<(), String> {
+        let (cmd, args) = match split_command(line.trim()) {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        match self.run_builtin_command(&cmd, &args) {
+            Err(err) if err == "custom" => self.run_custom_command(line).inspect_err(|_| {
+                self.detach_process();
+            }),
+            ret => ret,
+        }
+    }
+
     pub fn run(&mut self) -> Result<(), String> {
-        self.init_reader()?;
+        let reader = self
+            .reader
+            .clone()
+            .ok_or("interactive mode requires a terminal".to_owned())?;
         loop {
-            let line: String;
-            {
-                line = self
-                    .reader
-                    .lock()
-                    .expect("lock reader failed")
-                    .read()
-                    .expect("read failed");
-            }
+            let line = reader
+                .lock()
+                .expect("lock reader failed")
+                .read()
+                .expect("read failed");
             if line.is_empty() {
                 continue;
             }
-            if let Some((cmd, args)) = split_command(line.trim()) {
-                if let Err(err) = self.run_builtin_command(&cmd, &parse_arguments(&args)) {
-                    match err.as_str() {
-                        "exit" => break,
-                        "custom" => {
-                            if let Err(err) = self.run_custom_command(&line) {
-                                println!("Error: {}", err);
-                                self.detach_process()
-                            }
-                            sleep(Duration::from_millis(10));
-                        }
-                        _ => {
-                            println!("Error: {}", err);
-                        }
-                    }
-                }
+            match self.execute_line(&line) {
+                Err(err) if err == "exit" => break,
+                Err(err) => println!("Error: {}", err),
+                Ok(()) => {}
             }
         }
 
         Ok(())
     }
-}
 
+    /// 依次执行多行命令，遇到失败的命令时停止，空行和以 `#` 开头的行被忽略。
+    ///
+    /// 返回退出状态：全部成功时为 0，命令执行失败时为 1，客户端出错（如连接断开、
+    /// 内置命令的参数错误）时为 2。
+    pub fn run_script(&mut self, lines: impl IntoIterator<Item = String>) -> i32 {
+        for line in lines {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            match self.execute_line(&line) {
+                Err(err) if err == "exit" => break,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    return 2;
+                }
+                Ok(()) if self.last_status != 0 => return self.last_status,
+                Ok(()) => {}
+            }
+        }
+        0
+    }
+}
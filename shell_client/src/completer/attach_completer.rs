@@ -1,7 +1,7 @@
-//! pad 命令的完成器，用于完成进程列表
+//! attach 命令的完成器，用于完成可以连接的进程列表
 use linefeed::{complete::Completion, prompter::Prompter, terminal::DefaultTerminal};
 
-use crate::{completer::Completer, sys};
+use crate::{completer::Completer, ShellProcess};
 
 pub struct AttachCommandCompleter;
 
@@ -13,7 +13,11 @@ impl linefeed::complete::Completer<DefaultTerminal> for AttachCommandCompleter {
         _start: usize,
         _end: usize,
     ) -> Option<Vec<Completion>> {
-        crate::completer::gen_autocomplete_item(&sys::get_process_list(), word)
+        let processes: Vec<(String, String)> = ShellProcess::list()
+            .into_iter()
+            .map(|process| (process.pid.to_string(), process.name))
+            .collect();
+        crate::completer::gen_autocomplete_item(&processes, word)
     }
 }
 
@@ -20,19 +20,26 @@ pub trait Completer: linefeed::complete::Completer<DefaultTerminal> {
     fn filter(w: &str, b: &str) -> bool
     where
         Self: Sized;
+    #[allow(clippy::new_ret_no_self)]
     fn new() -> Box<dyn Completer>
     where
         Self: Sized;
 }
 
+/// 自动完成数据的匹配规则，返回 0 表示匹配第一项，1 表示匹配第二项，-1 表示不匹配
+type MatchOp = Box<dyn Fn(&(String, String), &str) -> i32>;
+
+/// 完成器过滤函数，见 [`Completer::filter`]
+pub type CompleterFilter = fn(&str, &str) -> bool;
+
 /// 生成自动完成条目
 /// cmp_data 自动完成数据
 /// word 输入
 pub fn gen_autocomplete_item(
-    cmp_data: &Vec<(String, String)>,
+    cmp_data: &[(String, String)],
     word: &str,
 ) -> Option<Vec<Completion>> {
-    let ops: Vec<Box<dyn Fn(&(String, String), &str) -> i32>> = vec![
+    let ops: Vec<MatchOp> = vec![
         Box::new(|x: &(String, String), word: &str| -> i32 {
             if tools::is_prefix(&x.0, word) {
                 0
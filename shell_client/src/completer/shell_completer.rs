@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    completer::{Completer, AttachCommandCompleter, PathCompleter},
+    completer::{AttachCommandCompleter, Completer, CompleterFilter, PathCompleter},
     tools,
 };
 
@@ -16,7 +16,7 @@ use linefeed::{
 
 pub struct ShellCompleter {
     pub autocomplete_data: Mutex<Cell<Vec<(String, String)>>>,
-    pub completer_chain: Vec<(fn(&str, &str) -> bool, Box<dyn Completer>)>,
+    pub completer_chain: Vec<(CompleterFilter, Box<dyn Completer>)>,
 }
 
 impl linefeed::complete::Completer<DefaultTerminal> for ShellCompleter {
@@ -27,39 +27,124 @@ impl linefeed::complete::Completer<DefaultTerminal> for ShellCompleter {
         start: usize,
         end: usize,
     ) -> Option<Vec<Completion>> {
-        self.completer_chain
-            .iter()
-            .filter(|x| x.0(word, prompter.buffer().trim()))
-            .find_map(|c| c.1.complete(word, prompter, start, end).filter(|x| !x.is_empty()))
+        self.namespace_complete(word, &prompter.buffer()[..start])
+            .or_else(|| {
+                self.completer_chain
+                    .iter()
+                    .filter(|x| x.0(word, prompter.buffer().trim()))
+                    .find_map(|c| {
+                        c.1.complete(word, prompter, start, end)
+                            .filter(|x| !x.is_empty())
+                    })
+            })
             .map_or_else(|| self.debug_command_complete(word), Some)
     }
 }
 
+/// 将匹配的命令折叠到下一级命名空间。
+///
+/// `start` 为补全内容在命令名中的起始位置，`offset` 之后遇到的第一个 `.` 之前的部分作为补全内容，
+/// 存在 `.` 时该项是一个命名空间，`keep_dot` 决定补全内容是否保留 `.`。
+fn collapse_namespace<'a>(
+    items: impl Iterator<Item = &'a (String, String)>,
+    start: usize,
+    offset: usize,
+    keep_dot: bool,
+) -> Vec<Completion> {
+    let mut ret: Vec<Completion> = vec![];
+    for (name, display) in items {
+        let completion = match name[offset..].find('.') {
+            Some(idx) => {
+                let end = offset + idx;
+                let group = &name[start..end];
+                if ret
+                    .iter()
+                    .any(|x| x.completion.trim_end_matches('.') == group)
+                {
+                    continue;
+                }
+                Completion {
+                    completion: if keep_dot {
+                        name[start..=end].to_owned()
+                    } else {
+                        group.to_owned()
+                    },
+                    display: Some(format!("{}.*", &name[..end])),
+                    suffix: if keep_dot {
+                        Suffix::None
+                    } else {
+                        Suffix::Default
+                    },
+                }
+            }
+            None => Completion {
+                completion: name[start..].to_owned(),
+                display: Some(display.clone()),
+                suffix: Suffix::Default,
+            },
+        };
+        ret.push(completion);
+    }
+    ret
+}
+
 impl ShellCompleter {
-    pub fn debug_command_complete(&self, word: &str) -> Option<Vec<Completion>> {
-        for cmp in [
-            tools::is_prefix,
-            tools::is_prefix_nocase,
-            tools::contain_nocase,
-        ] {
-            let ret = self
-                .autocomplete_data
+    /// 以空格分隔的命名空间补全，如 `db pool st` 补全 `db.pool` 下以 `st` 开头的命令。
+    fn namespace_complete(&self, word: &str, before: &str) -> Option<Vec<Completion>> {
+        let namespace = before.split_whitespace().collect::<Vec<&str>>().join(".");
+        if namespace.is_empty() {
+            return None;
+        }
+        let prefix = format!("{}.", namespace);
+        let typed = format!("{}{}", prefix, word);
+        let ret = collapse_namespace(
+            self.autocomplete_data
                 .lock()
                 .expect("lock autocomplete data failed")
                 .get_mut()
                 .iter()
-                .filter(|x| cmp(&x.0, word))
-                .map(|x| Completion {
-                    completion: x.0.clone(),
-                    display: Some(x.1.clone()),
-                    suffix: Suffix::Default,
-                })
-                .collect::<Vec<Completion>>();
+                .filter(|x| x.0.starts_with(&typed)),
+            prefix.len(),
+            typed.len(),
+            false,
+        );
+        if ret.is_empty() {
+            None
+        } else {
+            Some(ret)
+        }
+    }
+
+    pub fn debug_command_complete(&self, word: &str) -> Option<Vec<Completion>> {
+        let mut data = self
+            .autocomplete_data
+            .lock()
+            .expect("lock autocomplete data failed");
+        let data = data.get_mut();
+
+        // 前缀匹配时只补全到下一级命名空间
+        for cmp in [tools::is_prefix, tools::is_prefix_nocase] {
+            let ret =
+                collapse_namespace(data.iter().filter(|x| cmp(&x.0, word)), 0, word.len(), true);
             if !ret.is_empty() {
                 return Some(ret);
             }
         }
-        None
+
+        let ret = data
+            .iter()
+            .filter(|x| tools::contain_nocase(&x.0, word))
+            .map(|x| Completion {
+                completion: x.0.clone(),
+                display: Some(x.1.clone()),
+                suffix: Suffix::Default,
+            })
+            .collect::<Vec<Completion>>();
+        if ret.is_empty() {
+            None
+        } else {
+            Some(ret)
+        }
     }
 
     pub fn set_autocomplete_data(&self, data: Vec<(String, String)>) {
@@ -0,0 +1,208 @@
+//! 以程序的方式调用进程中的命令
+//!
+//! [`Connection`] 通过 JSON-RPC 2.0 与进程通信，命令的返回值按 JSON 解码为指定的类型，
+//! 命令执行过程中的输出和错误输出随结果一起返回，适用于集成测试和运维工具。
+
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::discovery::ShellProcess;
+
+/// 连接进程的 Unix 域套接字，套接字不存在或是崩溃的进程遗留的时给出明确的错误信息。
+pub(crate) fn connect(path: &Path) -> Result<UnixStream, String> {
+    UnixStream::connect(path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => format!(
+            "{} not found, the process does not serve shell commands",
+            path.display()
+        ),
+        ErrorKind::ConnectionRefused => format!(
+            "{} is stale, the process that created it is no longer serving",
+            path.display()
+        ),
+        _ => format!("connect {} failed: {}", path.display(), err),
+    })
+}
+
+/// 命令执行成功的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallOutput<T> {
+    /// 命令的返回值
+    pub value: T,
+
+    /// 命令执行过程中的输出
+    pub stdout: String,
+
+    /// 命令执行过程中的错误输出
+    pub stderr: String,
+}
+
+/// 调用失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallError {
+    /// JSON-RPC 的错误码，如命令不存在为 -32601，参数错误为 -32602，命令执行失败为 -32000，
+    /// 连接失败或返回值无法解码时为 0
+    pub code: i64,
+
+    /// 错误信息
+    pub message: String,
+
+    /// 命令执行过程中的输出
+    pub stdout: String,
+
+    /// 命令执行过程中的错误输出，命令 panic 时包含 panic 的信息和调用栈
+    pub stderr: String,
+}
+
+impl Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CallError {}
+
+impl From<String> for CallError {
+    fn from(message: String) -> Self {
+        CallError {
+            code: 0,
+            message,
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+}
+
+impl From<CallError> for String {
+    fn from(err: CallError) -> Self {
+        err.message
+    }
+}
+
+/// 与一个进程的连接
+///
+/// # 示例
+///
+/// ```rust,no_run
+/// use shell_client::Connection;
+///
+/// fn main() -> Result<(), String> {
+///     let mut conn = Connection::attach(1234)?;
+///     let sum = conn.call::<i64>("add_two", (1, 2))?;
+///     assert_eq!(sum.value, 3);
+///     assert_eq!(sum.stdout, "1 + 2 = 3\n");
+///
+///     // 使用对象按名称传递参数
+///     let scaled = conn.call::<f64>("scale", serde_json::json!({"value": 3, "round": true}))?;
+///     assert_eq!(scaled.value, 3.0);
+///     Ok(())
+/// }
+/// ```
+pub struct Connection {
+    conn: UnixStream,
+    reader: BufReader<UnixStream>,
+    /// 下一个请求的 id
+    next_id: u64,
+}
+
+impl Connection {
+    /// 连接到进程 id 为 `pid` 的进程，进程中只能有一个服务端实例，容器中的进程使用主机上的进程 id。
+    pub fn attach(pid: u32) -> Result<Connection, String> {
+        Connection::connect(ShellProcess::find(pid, None)?.socket)
+    }
+
+    /// 连接到进程 id 为 `pid` 的进程中名称为 `name` 的服务端实例。
+    pub fn attach_instance(pid: u32, name: &str) -> Result<Connection, String> {
+        Connection::connect(ShellProcess::find(pid, Some(name))?.socket)
+    }
+
+    /// 连接到指定路径的 Unix 域套接字。
+    pub fn connect(path: impl AsRef<Path>) -> Result<Connection, String> {
+        let conn = connect(path.as_ref())?;
+        Ok(Connection {
+            reader: BufReader::new(conn.try_clone().map_err(|err| err.to_string())?),
+            conn,
+            next_id: 1,
+        })
+    }
+
+    /// 调用一个命令，等待命令执行完成并将返回值解码为 `T`。
+    ///
+    /// `args` 序列化为数组（如元组）时按位置传递，序列化为对象时按名称传递，
+    /// 没有参数时使用 `()`，其他值作为唯一的参数。
+    pub fn call<T: DeserializeOwned>(
+        &mut self,
+        name: &str,
+        args: impl Serialize,
+    ) -> Result<CallOutput<T>, CallError> {
+        let params = match serde_json::to_value(args).map_err(|err| err.to_string())? {
+            Value::Null => Value::Array(vec![]),
+            params @ (Value::Array(_) | Value::Object(_)) => params,
+            value => Value::Array(vec![value]),
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": name, "params": params});
+        self.conn
+            .write_all(format!("{}\n", request).as_bytes())
+            .map_err(|err| err.to_string())?;
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        loop {
+            let mut line = String::new();
+            if self
+                .reader
+                .read_line(&mut line)
+                .map_err(|err| err.to_string())?
+                == 0
+            {
+                return Err("connection closed".to_owned().into());
+            }
+            let mut message: Value = serde_json::from_str(&line).map_err(|err| err.to_string())?;
+
+            if message["method"] == "output" {
+                let params = &message["params"];
+                if params["id"] == id {
+                    let text = params["text"].as_str().unwrap_or_default();
+                    match params["stream"].as_str() {
+                        Some("stderr") => stderr.push_str(text),
+                        _ => stdout.push_str(text),
+                    }
+                }
+                continue;
+            }
+            if message["id"] != id {
+                continue;
+            }
+
+            if let Some(error) = message.get("error") {
+                return Err(CallError {
+                    code: error["code"].as_i64().unwrap_or_default(),
+                    message: error["message"].as_str().unwrap_or_default().to_owned(),
+                    stdout,
+                    stderr,
+                });
+            }
+            return match serde_json::from_value(message["result"].take()) {
+                Ok(value) => Ok(CallOutput {
+                    value,
+                    stdout,
+                    stderr,
+                }),
+                Err(err) => Err(CallError {
+                    code: 0,
+                    message: format!("decode result failed: {}", err),
+                    stdout,
+                    stderr,
+                }),
+            };
+        }
+    }
+}
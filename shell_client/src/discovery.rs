@@ -0,0 +1,194 @@
+//! 查找可以连接的进程
+//!
+//! 服务端启动时写入发现记录（见 [`DiscoveryRecord`]），只有记录仍然有效的服务端实例才会被列出，
+//! 进程名称从 `/proc` 中读取，读取失败时使用记录中的名称。
+//!
+//! 容器等其他挂载命名空间中的进程在自己的 `/tmp` 等目录中写入记录，客户端通过
+//! `/proc/<pid>/root` 读取这些记录并连接其中的套接字，记录中的进程 id 是容器中的 id，
+//! 按 `/proc/<pid>/status` 中的 `NSpid` 转换为当前命名空间中的 id。
+
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use shell_core::{lock_path, process_owner, search_dirs_with, DiscoveryRecord};
+
+use crate::sys::{self, ProcessInfo};
+
+/// 一个可以连接的服务端实例
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellProcess {
+    /// 进程 id
+    pub pid: u32,
+
+    /// 进程名称
+    pub name: String,
+
+    /// 从 `/proc` 中读取的进程信息，无法读取时为 `None`
+    pub process: Option<ProcessInfo>,
+
+    /// 服务端实例名称
+    pub instance: String,
+
+    /// 服务端的名称和版本
+    pub version: String,
+
+    /// 套接字路径，其他挂载命名空间中的套接字为 `/proc/<pid>/root` 下的路径
+    pub socket: PathBuf,
+
+    /// 服务端的启动时间，自 UNIX 纪元以来的秒数
+    pub started: u64,
+}
+
+/// 将其他挂载命名空间中的路径转换为通过根目录 `root` 访问的路径
+fn in_root(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+impl ShellProcess {
+    fn from_record(
+        record: DiscoveryRecord,
+        pid: u32,
+        process: Option<&ProcessInfo>,
+    ) -> ShellProcess {
+        ShellProcess {
+            pid,
+            name: process.map_or(record.process_name, |process| process.comm.clone()),
+            process: process.cloned(),
+            instance: record.name,
+            version: record.version,
+            socket: record.socket,
+            started: record.started,
+        }
+    }
+
+    /// 列出所有正在运行的服务端实例，包括容器中的实例，按进程 id 和实例名称排序。
+    ///
+    /// 容器的查找目录可能挂载自宿主机的目录，其中的记录是容器中的进程 id，在当前命名空间中属于
+    /// 其他进程，因此只保留锁由记录中的进程持有的记录，容器中的实例由
+    /// [`ShellProcess::list_namespaces`] 按 `NSpid` 转换后列出。
+    pub fn list() -> Vec<ShellProcess> {
+        // 只读取有记录的进程的信息
+        let records: Vec<DiscoveryRecord> = DiscoveryRecord::scan_all()
+            .into_iter()
+            .filter(|record| {
+                record.is_alive()
+                    && sys::flock_holders(&lock_path(&record.socket)).contains(&record.pid)
+            })
+            .collect();
+        let pids: Vec<u32> = records.iter().map(|record| record.pid).collect();
+        let processes = ProcessInfo::read_all(&pids);
+        let mut shells: Vec<ShellProcess> = records
+            .into_iter()
+            .map(|record| {
+                let pid = record.pid;
+                let process = processes.iter().find(|process| process.pid == pid);
+                ShellProcess::from_record(record, pid, process)
+            })
+            .collect();
+        shells.extend(ShellProcess::list_namespaces());
+        // 排序是稳定的，同一个实例同时出现在当前命名空间和 /proc/<pid>/root 中时保留前者
+        shells.sort_by(|a, b| (a.pid, &a.instance).cmp(&(b.pid, &b.instance)));
+        shells.dedup_by(|a, b| (a.pid, &a.instance) == (b.pid, &b.instance));
+        shells
+    }
+
+    /// 列出其他挂载命名空间中的服务端实例，没有权限访问的命名空间被忽略。
+    ///
+    /// 每个命名空间只通过其中进程 id 最小的进程（通常是容器的第一个进程）访问根目录，
+    /// 查找目录由该进程的环境变量决定。
+    fn list_namespaces() -> Vec<ShellProcess> {
+        let own = sys::mount_namespace(None);
+        let mut pids = sys::pids();
+        pids.sort();
+        let mut namespaces: HashMap<PathBuf, Vec<u32>> = HashMap::new();
+        for pid in pids {
+            match sys::mount_namespace(Some(pid)) {
+                Some(ns) if Some(&ns) != own.as_ref() => {
+                    namespaces.entry(ns).or_default().push(pid)
+                }
+                _ => {}
+            }
+        }
+
+        let mut shells = vec![];
+        for members in namespaces.values() {
+            let root = PathBuf::from(format!("/proc/{}/root", members[0]));
+            let env = sys::environ(members[0]);
+            let dirs: HashSet<PathBuf> = search_dirs_with(|name| env.get(name).cloned())
+                .into_iter()
+                .collect();
+            // 命名空间中进程的 NSpid 只在找到记录时读取一次
+            let ns_pids: OnceCell<Vec<(u32, u32)>> = OnceCell::new();
+            // 记录中是进程在其 PID 命名空间中的 id
+            let host_pid = |ns_pid: u32| {
+                ns_pids
+                    .get_or_init(|| {
+                        members
+                            .iter()
+                            .filter_map(|pid| Some((*pid, sys::ns_pid(*pid)?)))
+                            .collect()
+                    })
+                    .iter()
+                    .find(|(_, id)| *id == ns_pid)
+                    .map(|(pid, _)| *pid)
+            };
+            for dir in dirs {
+                let records = DiscoveryRecord::scan_in(&root, &dir, |ns_pid| {
+                    process_owner(host_pid(ns_pid)?)
+                });
+                for mut record in records {
+                    let pid = match host_pid(record.pid) {
+                        Some(pid) => pid,
+                        None => continue,
+                    };
+                    // 通过服务端进程自身的根目录访问套接字，不依赖命名空间中的其他进程
+                    record.socket =
+                        in_root(Path::new(&format!("/proc/{}/root", pid)), &record.socket);
+                    if record.is_alive() {
+                        let process = ProcessInfo::read(pid);
+                        shells.push(ShellProcess::from_record(record, pid, process.as_ref()));
+                    }
+                }
+            }
+        }
+        shells
+    }
+
+    /// 查找进程 `pid` 中名称为 `instance` 的服务端实例，不指定名称时进程中只能有一个实例。
+    pub fn find(pid: u32, instance: Option<&str>) -> Result<ShellProcess, String> {
+        let mut shells: Vec<ShellProcess> = ShellProcess::list()
+            .into_iter()
+            .filter(|shell| shell.pid == pid)
+            .filter(|shell| instance.is_none_or(|name| shell.instance == name))
+            .collect();
+        match shells.len() {
+            // 给出进程没有侦听或记录已经失效的具体原因
+            0 => Err(DiscoveryRecord::resolve(pid, instance)
+                .err()
+                .unwrap_or(format!("process {} does not serve shell commands", pid))),
+            1 => Ok(shells.remove(0)),
+            _ => Err(format!(
+                "process {} has multiple shell instances: {}",
+                pid,
+                shells
+                    .iter()
+                    .map(|shell| shell.instance.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// 服务端已经运行的时间
+    pub fn uptime(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Duration::from_secs(now.saturating_sub(self.started))
+    }
+}
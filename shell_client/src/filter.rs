@@ -0,0 +1,146 @@
+//! attach 命令的进程过滤条件
+//!
+//! 按位置传递的第一个参数为进程 id 或进程名称的前缀，第二个参数为实例名称，
+//! 也可以按名称组合多个条件，如 `attach name=worker,user=svc`：
+//!
+//! | 名称       | 匹配                             |
+//! |------------|----------------------------------|
+//! | `pid`      | 进程 id                          |
+//! | `name`     | 进程名称的前缀                   |
+//! | `cmdline`  | 完整命令行中的子串               |
+//! | `regex`    | 进程名称或完整命令行的正则表达式 |
+//! | `user`     | 进程的用户名或用户 id            |
+//! | `ppid`     | 父进程 id                        |
+//! | `instance` | 服务端实例名称                   |
+
+use regex::Regex;
+use shell_core::{Argument, NamedArgument};
+
+use crate::discovery::ShellProcess;
+
+/// 进程过滤条件，所有指定的条件都满足时匹配
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_client::ProcessFilter;
+/// use shell_core::parse_named_arguments;
+///
+/// let filter = ProcessFilter::parse(&parse_named_arguments("name=worker,user=svc")).unwrap();
+/// assert_eq!(filter.name.as_deref(), Some("worker"));
+/// assert_eq!(filter.user.as_deref(), Some("svc"));
+///
+/// assert!(ProcessFilter::parse(&parse_named_arguments("regex=(")).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProcessFilter {
+    /// 进程 id
+    pub pid: Option<u32>,
+
+    /// 进程名称的前缀
+    pub name: Option<String>,
+
+    /// 完整命令行中的子串
+    pub cmdline: Option<String>,
+
+    /// 匹配进程名称或完整命令行的正则表达式
+    pub regex: Option<Regex>,
+
+    /// 用户名或用户 id
+    pub user: Option<String>,
+
+    /// 父进程 id
+    pub ppid: Option<u32>,
+
+    /// 服务端实例名称
+    pub instance: Option<String>,
+}
+
+fn parse_pid(name: &str, value: &Argument) -> Result<u32, String> {
+    let pid = match value {
+        Argument::Int(i, _) => u32::try_from(*i).ok(),
+        Argument::UInt(u, _) => u32::try_from(*u).ok(),
+        other => other.to_string().parse().ok(),
+    };
+    pid.ok_or_else(|| format!("invalid {}: {}", name, value))
+}
+
+impl ProcessFilter {
+    /// 解析 attach 命令的参数，没有任何条件时返回错误。
+    pub fn parse(args: &[NamedArgument]) -> Result<ProcessFilter, String> {
+        let mut filter = ProcessFilter::default();
+        let mut position = 0;
+        for arg in args {
+            let value = &arg.value;
+            match arg.name.as_deref() {
+                None => {
+                    position += 1;
+                    match (position, value) {
+                        (1, Argument::Int(..) | Argument::UInt(..)) => {
+                            filter.pid = Some(parse_pid("pid", value)?)
+                        }
+                        (1, _) => filter.name = Some(value.to_string()),
+                        (2, _) => filter.instance = Some(value.to_string()),
+                        _ => return Err("argument number error".to_owned()),
+                    }
+                }
+                Some("pid") => filter.pid = Some(parse_pid("pid", value)?),
+                Some("ppid") => filter.ppid = Some(parse_pid("ppid", value)?),
+                Some("name") => filter.name = Some(value.to_string()),
+                Some("cmdline") => filter.cmdline = Some(value.to_string()),
+                Some("regex") => {
+                    filter.regex = Some(
+                        Regex::new(&value.to_string())
+                            .map_err(|err| format!("invalid regex: {}", err))?,
+                    )
+                }
+                Some("user") => filter.user = Some(value.to_string()),
+                Some("instance") => filter.instance = Some(value.to_string()),
+                Some(other) => {
+                    return Err(format!(
+                        "unknown filter `{}`, expected pid, name, cmdline, regex, user, ppid or instance",
+                        other
+                    ))
+                }
+            }
+        }
+        if args.is_empty() {
+            return Err("argument number error".to_owned());
+        }
+        Ok(filter)
+    }
+
+    /// 服务端实例是否满足所有条件，无法读取进程信息时只匹配进程 id、名称和实例名称。
+    pub fn matches(&self, shell: &ShellProcess) -> bool {
+        let process = shell.process.as_ref();
+        let command = process.map(|process| process.command());
+        self.pid.is_none_or(|pid| shell.pid == pid)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| shell.name.starts_with(name.as_str()))
+            && self.cmdline.as_ref().is_none_or(|cmdline| {
+                command
+                    .as_ref()
+                    .is_some_and(|command| command.contains(cmdline.as_str()))
+            })
+            && self.regex.as_ref().is_none_or(|regex| {
+                regex.is_match(&shell.name)
+                    || command
+                        .as_ref()
+                        .is_some_and(|command| regex.is_match(command))
+            })
+            && self.user.as_ref().is_none_or(|user| {
+                process.is_some_and(|process| {
+                    &process.user == user || process.uid.to_string() == *user
+                })
+            })
+            && self
+                .ppid
+                .is_none_or(|ppid| process.is_some_and(|process| process.ppid == ppid))
+            && self
+                .instance
+                .as_ref()
+                .is_none_or(|instance| &shell.instance == instance)
+    }
+}
@@ -1,6 +1,12 @@
 mod autocomplete_reader;
 mod client;
 mod completer;
-mod sys;
+mod connection;
+mod discovery;
+mod filter;
+pub mod sys;
 mod tools;
 pub use client::*;
+pub use connection::*;
+pub use discovery::*;
+pub use filter::*;
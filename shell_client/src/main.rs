@@ -1,5 +1,115 @@
 use shell_client::*;
+use shell_core::Argument;
+use std::io::{BufRead, IsTerminal};
+
+const USAGE: &str = "\
+Usage: shell_client [OPTIONS]
+
+Without options, start an interactive shell.
+
+Options:
+  -p, --pid <PID>        attach to the process with the pid
+  -n, --name <NAME>      attach to the process whose name starts with NAME
+  -i, --instance <NAME>  attach to the named shell instance of the process
+  -c, --command <LINE>   run a command and exit, can be given multiple times
+  -f, --file <FILE>      run commands from a file and exit, `-` reads from stdin
+  -h, --help             print this help
+
+Commands given with -c run before the commands in the file. If a process is
+given but no command, commands are read from stdin when it is not a terminal.
+
+Exit status: 0 if all commands succeed, 1 if a command fails, 2 on other errors.";
+
+/// 命令行选项
+#[derive(Default)]
+struct Options {
+    /// 要连接的进程
+    target: Option<Argument>,
+    /// 要连接的服务端实例名称
+    instance: Option<String>,
+    /// 通过 `-c` 指定的命令
+    commands: Vec<String>,
+    /// 从中读取命令的文件，`-` 表示标准输入
+    file: Option<String>,
+}
+
+fn parse_options(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "-p" | "--pid" => {
+                let raw = value()?;
+                let pid = raw.parse().map_err(|_| format!("invalid pid: {}", raw))?;
+                options.target = Some(Argument::UInt(pid, raw));
+            }
+            "-n" | "--name" => options.target = Some(Argument::Str(value()?)),
+            "-i" | "--instance" => options.instance = Some(value()?),
+            "-c" | "--command" => options.commands.push(value()?),
+            "-f" | "--file" => options.file = Some(value()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => return Err(format!("unknown option: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// 读取命令文件中的所有行，`-` 表示标准输入。
+fn read_lines(file: &str) -> Result<Vec<String>, String> {
+    let lines = match file {
+        "-" => std::io::stdin().lock().lines().collect(),
+        path => std::fs::read_to_string(path)
+            .map(|content| content.lines().map(str::to_owned).collect()),
+    };
+    lines.map_err(|err| format!("read {} failed: {}", file, err))
+}
 
 fn main() {
-    Client::new().run().unwrap();
+    let mut options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.target.is_some()
+        && options.commands.is_empty()
+        && options.file.is_none()
+        && !std::io::stdin().is_terminal()
+    {
+        options.file = Some("-".to_owned());
+    }
+
+    let interactive = options.commands.is_empty() && options.file.is_none();
+    let mut client = match interactive {
+        true => Client::new(),
+        false => Client::non_interactive(),
+    };
+    if let Some(target) = options.target {
+        if let Err(err) = client.attach(target, options.instance.as_deref()) {
+            eprintln!("Error: {}", err);
+            std::process::exit(2);
+        }
+    }
+
+    if interactive {
+        client.run().unwrap();
+        std::process::exit(client.last_status());
+    }
+
+    let mut lines = options.commands;
+    if let Some(file) = &options.file {
+        match read_lines(file) {
+            Ok(file_lines) => lines.extend(file_lines),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(2);
+            }
+        }
+    }
+    std::process::exit(client.run_script(lines));
 }
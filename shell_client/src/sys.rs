@@ -1,29 +1,259 @@
 /*!
  * 系统功能封装
+ *
+ * 进程信息从 `/proc` 中读取，不依赖 `ps` 等外部命令。
  */
 
-use std::process;
-
-
-/// 获取进程列表
-pub fn get_process_list() -> Vec<(String, String)> {
-    String::from_utf8(
-        process::Command::new("ps")
-            .args(["-A", "-o", "pid,comm"])
-            .output()
-            .unwrap()
-            .stdout,
-    )
-    .expect("decode failed")
-    .split("\n")
-    .skip(1)
-    .filter_map(|x| {
-        let sp:Vec<&str> = x.split_whitespace().collect();
-        if sp.len() == 2 {
-            Some((sp[0].to_owned(),sp[1].to_owned()))
-        } else {
-            None
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs,
+    os::unix::{ffi::OsStringExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+
+/// 进程信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    /// 进程 id
+    pub pid: u32,
+
+    /// 父进程 id
+    pub ppid: u32,
+
+    /// 进程在其所在的 PID 命名空间（如容器）中的 id，不在子命名空间中时与 `pid` 相同
+    pub ns_pid: u32,
+
+    /// 进程名称，即 `/proc/<pid>/comm`，最长 15 个字节
+    pub comm: String,
+
+    /// 完整的命令行参数，内核线程为空
+    pub cmdline: Vec<String>,
+
+    /// 进程的实际用户 id
+    pub uid: u32,
+
+    /// 进程的实际用户，无法解析用户名时为用户 id
+    pub user: String,
+
+    /// 进程的启动时间，自 UNIX 纪元以来的秒数
+    pub start_time: u64,
+}
+
+/// 读取进程信息时共用的系统信息，读取多个进程时只读取一次
+struct SystemInfo {
+    /// 用户 id 到用户名的映射
+    users: HashMap<u32, String>,
+
+    /// 系统的启动时间，自 UNIX 纪元以来的秒数
+    boot_time: u64,
+
+    /// 每秒的时钟节拍数
+    clock_ticks: u64,
+}
+
+impl SystemInfo {
+    fn read() -> SystemInfo {
+        SystemInfo {
+            users: users(),
+            boot_time: boot_time(),
+            clock_ticks: clock_ticks(),
+        }
+    }
+}
+
+impl ProcessInfo {
+    /// 读取进程 `pid` 的信息，进程不存在或已经退出时返回 `None`。
+    pub fn read(pid: u32) -> Option<ProcessInfo> {
+        ProcessInfo::read_with(pid, &SystemInfo::read())
+    }
+
+    /// 读取多个进程的信息，按进程 id 排序，不存在或已经退出的进程被忽略。
+    pub fn read_all(pids: &[u32]) -> Vec<ProcessInfo> {
+        let system = SystemInfo::read();
+        let mut processes: Vec<ProcessInfo> = pids
+            .iter()
+            .filter_map(|pid| ProcessInfo::read_with(*pid, &system))
+            .collect();
+        processes.sort_by_key(|process| process.pid);
+        processes
+    }
+
+    fn read_with(pid: u32, system: &SystemInfo) -> Option<ProcessInfo> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // 进程名称中可能包含空格和括号，以最后一个右括号为界
+        let (head, tail) = stat.rsplit_once(')')?;
+        let comm = head.split_once('(')?.1.to_owned();
+        // 右括号之后从第 3 个字段 state 开始
+        let fields: Vec<&str> = tail.split_whitespace().collect();
+        let ppid = fields.get(1)?.parse().ok()?;
+        let start_ticks: u64 = fields.get(19)?.parse().ok()?;
+
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid))
+            .map(|content| {
+                content
+                    .split(|b| *b == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .map(str::split_whitespace)
+        };
+        let uid = field("Uid:")?.next()?.parse().ok()?;
+        // 进程在各级 PID 命名空间中的 id，最后一个为进程所在命名空间中的 id
+        let ns_pid = parse_ns_pid(&status).unwrap_or(pid);
+
+        Some(ProcessInfo {
+            pid,
+            ppid,
+            ns_pid,
+            comm,
+            cmdline,
+            uid,
+            user: system.users.get(&uid).cloned().unwrap_or(uid.to_string()),
+            start_time: system.boot_time + start_ticks / system.clock_ticks,
+        })
+    }
+
+    /// 进程的命令行，内核线程使用 `[comm]` 表示
+    pub fn command(&self) -> String {
+        match self.cmdline.is_empty() {
+            true => format!("[{}]", self.comm),
+            false => self.cmdline.join(" "),
         }
-    })
-    .collect()
+    }
+}
+
+/// 获取进程列表，读取过程中退出的进程被忽略
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_client::sys::{process_list, ProcessInfo};
+///
+/// let me = ProcessInfo::read(std::process::id()).unwrap();
+/// assert!(process_list().contains(&me));
+/// assert_eq!(me.ppid, std::os::unix::process::parent_id());
+/// ```
+pub fn process_list() -> Vec<ProcessInfo> {
+    ProcessInfo::read_all(&pids())
+}
+
+/// 所有进程的 id，只读取 `/proc` 目录，不读取进程信息
+pub fn pids() -> Vec<u32> {
+    match fs::read_dir("/proc") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// 进程在其所在的 PID 命名空间中的 id，只读取 `/proc/<pid>/status`，无法读取时返回 `None`
+pub fn ns_pid(pid: u32) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    Some(parse_ns_pid(&status).unwrap_or(pid))
+}
+
+/// 从 `/proc/<pid>/status` 的内容中解析 `NSpid`，最后一个为进程所在命名空间中的 id
+fn parse_ns_pid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))?
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()
+}
+
+/// 进程所在的挂载命名空间，`None` 表示当前进程，无法读取时返回 `None`
+pub fn mount_namespace(pid: Option<u32>) -> Option<PathBuf> {
+    let pid = pid.map_or("self".to_owned(), |pid| pid.to_string());
+    fs::read_link(format!("/proc/{}/ns/mnt", pid)).ok()
+}
+
+/// 持有文件 `path` 上的 `flock` 锁的进程，进程 id 为当前 PID 命名空间中的 id，
+/// 从 `/proc/locks` 中读取，无法读取时为空
+pub fn flock_holders(path: &Path) -> Vec<u32> {
+    let (Ok(meta), Ok(locks)) = (
+        fs::symlink_metadata(path),
+        fs::read_to_string("/proc/locks"),
+    ) else {
+        return vec![];
+    };
+    // 主次设备号按 Linux 的设备号编码取出，以十六进制显示，inode 为十进制
+    let dev = meta.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let file = format!("{:02x}:{:02x}:{}", major, minor, meta.ino());
+    parse_flock_holders(&locks, &file)
+}
+
+/// 从 `/proc/locks` 的内容中解析持有文件 `file`（`主设备号:次设备号:inode`）上的 `flock` 锁的进程，
+/// 等待锁的进程（以 `->` 标记）不包括在内
+fn parse_flock_holders(locks: &str, file: &str) -> Vec<u32> {
+    locks
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter_map(|fields| match fields[..] {
+            [_, "FLOCK", _, _, pid, id, ..] if id == file => pid.parse().ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 进程的环境变量，无法读取时为空
+pub fn environ(pid: u32) -> HashMap<String, OsString> {
+    fs::read(format!("/proc/{}/environ", pid))
+        .unwrap_or_default()
+        .split(|b| *b == 0)
+        .filter_map(|var| {
+            let pos = var.iter().position(|b| *b == b'=')?;
+            Some((
+                String::from_utf8_lossy(&var[..pos]).into_owned(),
+                OsString::from_vec(var[pos + 1..].to_vec()),
+            ))
+        })
+        .collect()
+}
+
+/// 系统的启动时间，自 UNIX 纪元以来的秒数
+fn boot_time() -> u64 {
+    fs::read_to_string("/proc/stat")
+        .ok()
+        .and_then(|stat| {
+            stat.lines()
+                .find_map(|line| line.strip_prefix("btime"))
+                .and_then(|btime| btime.trim().parse().ok())
+        })
+        .unwrap_or_default()
+}
+
+/// 每秒的时钟节拍数，`/proc/<pid>/stat` 中的时间以此为单位
+fn clock_ticks() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+/// 用户 id 到用户名的映射，读取 `/etc/passwd`
+fn users() -> HashMap<u32, String> {
+    fs::read_to_string("/etc/passwd")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_owned()))
+        })
+        .collect()
 }
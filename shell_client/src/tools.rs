@@ -15,8 +15,16 @@ pub fn is_prefix_nocase(src: &str, prefix: &str) -> bool {
 /// 判断一个字符串是否是另一个字符串的子串，忽略大小写
 pub fn contain_nocase(src: &str, substr: &str) -> bool {
     src.len() >= substr.len()
-        && src
-            .to_uppercase()
-            .find(&substr.to_uppercase())
-            .map_or(false, |_| true)
+        && src.to_uppercase().contains(&substr.to_uppercase())
+}
+
+/// 将运行时间格式化为 `2d03h`、`1h05m`、`3m20s` 的形式
+pub fn format_uptime(uptime: std::time::Duration) -> String {
+    let secs = uptime.as_secs();
+    match (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60) {
+        (0, 0, 0, s) => format!("{}s", s),
+        (0, 0, m, s) => format!("{}m{:02}s", m, s),
+        (0, h, m, _) => format!("{}h{:02}m", h, m),
+        (d, h, _, _) => format!("{}d{:02}h", d, h),
+    }
 }
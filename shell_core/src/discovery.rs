@@ -0,0 +1,338 @@
+//! 服务发现
+//!
+//! 服务端启动时在套接字目录中写入一条发现记录，包含进程 id、实例名称、版本和套接字路径，
+//! 客户端通过这些记录找到可以连接的进程，而不是自行拼接套接字路径。
+//!
+//! 套接字目录按以下顺序选择：
+//!
+//! 1. 环境变量 `RUST_SHELL_DIR`
+//! 2. `$XDG_RUNTIME_DIR/rust_shell`
+//! 3. `/tmp/rust_shell`
+//!
+//! 服务端在侦听期间锁定套接字对应的锁文件，进程退出后锁自动释放，客户端据此判断记录是否有效。
+//!
+//! 共享目录中的文件可能由其他用户放置，只读取普通文件且不跟随符号链接，打开文件时不会阻塞。
+//! 记录文件的所有者必须与记录中的进程的用户相同，没有指定套接字路径的记录的套接字必须在记录所在的目录中，
+//! 其他记录被忽略。
+
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Write},
+    os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// 覆盖套接字目录的环境变量
+pub const DIR_ENV: &str = "RUST_SHELL_DIR";
+
+/// 默认的实例名称
+pub const DEFAULT_INSTANCE: &str = "default";
+
+/// 没有 `XDG_RUNTIME_DIR` 时使用的目录，所有用户共享
+pub const SHARED_DIR: &str = "/tmp/rust_shell";
+
+/// 发现记录文件的扩展名
+const RECORD_EXTENSION: &str = "json";
+
+/// 客户端查找发现记录的目录，设置了 `RUST_SHELL_DIR` 时只使用该目录。
+pub fn search_dirs() -> Vec<PathBuf> {
+    search_dirs_with(|name| std::env::var_os(name))
+}
+
+/// 按环境变量 `var` 确定查找目录，用于查找其他进程（如容器中的进程）使用的目录。
+pub fn search_dirs_with(var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    if let Some(dir) = var(DIR_ENV) {
+        return vec![PathBuf::from(dir)];
+    }
+    let mut dirs = vec![];
+    if let Some(dir) = var("XDG_RUNTIME_DIR") {
+        dirs.push(PathBuf::from(dir).join("rust_shell"));
+    }
+    dirs.push(PathBuf::from(SHARED_DIR));
+    dirs
+}
+
+/// 服务端默认使用的套接字目录
+pub fn default_dir() -> PathBuf {
+    search_dirs().remove(0)
+}
+
+/// 套接字对应的锁文件
+pub fn lock_path(socket: &Path) -> PathBuf {
+    let mut path = socket.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// 实例名称是否可以用于文件名：只包含字母、数字、`-`、`_` 和 `.`，且不以 `.` 开头。
+pub fn is_valid_instance_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// 打开一个普通文件用于读取，不跟随符号链接，打开 FIFO 等文件时不会阻塞。
+fn open_regular(path: &Path) -> Option<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+        .open(path)
+        .ok()?;
+    file.metadata().ok()?.is_file().then_some(file)
+}
+
+/// 锁文件是否被其他进程锁定
+fn is_locked(lock: &Path) -> bool {
+    match open_regular(lock) {
+        Some(lock) => matches!(lock.try_lock(), Err(TryLockError::WouldBlock)),
+        None => false,
+    }
+}
+
+/// 进程的有效用户 id，即 `/proc/<pid>` 的所有者，进程不存在时返回 `None`
+pub fn process_owner(pid: u32) -> Option<u32> {
+    std::fs::metadata(format!("/proc/{}", pid))
+        .ok()
+        .map(|meta| meta.uid())
+}
+
+/// 进程是否存在
+fn process_exists(pid: u32) -> bool {
+    // 进程 id 0 表示当前进程组
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // 没有权限向进程发送信号时进程也存在
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// 文件是否为当前用户所有的普通文件或套接字，符号链接等其他文件返回 `false`
+fn is_own_file(path: &Path) -> bool {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) => {
+            (meta.file_type().is_file() || meta.file_type().is_socket())
+                && meta.uid() == unsafe { libc::geteuid() }
+        }
+        Err(_) => false,
+    }
+}
+
+/// 一个服务端实例的发现记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryRecord {
+    /// 进程 id
+    pub pid: u32,
+
+    /// 实例名称，同一个进程中的多个服务端以名称区分
+    pub name: String,
+
+    /// 进程名称
+    pub process_name: String,
+
+    /// 服务端的名称和版本，如 `shell_server 0.1.1`
+    pub version: String,
+
+    /// 套接字路径
+    pub socket: PathBuf,
+
+    /// 服务端的启动时间，自 UNIX 纪元以来的秒数
+    pub started: u64,
+
+    /// 套接字路径是否由服务端指定，未指定时套接字在记录所在的目录中
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub custom_socket: bool,
+}
+
+impl DiscoveryRecord {
+    /// 创建当前进程的发现记录。
+    pub fn new(name: &str, version: &str, socket: PathBuf) -> DiscoveryRecord {
+        DiscoveryRecord {
+            pid: std::process::id(),
+            name: name.to_owned(),
+            process_name: std::env::current_exe()
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                .unwrap_or_default(),
+            version: version.to_owned(),
+            socket,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            custom_socket: false,
+        }
+    }
+
+    /// 记录在目录 `dir` 中的文件路径
+    pub fn path_in(dir: &Path, pid: u32, name: &str) -> PathBuf {
+        dir.join(format!("{}.{}.{}", pid, name, RECORD_EXTENSION))
+    }
+
+    /// 没有指定套接字路径时，实例在目录 `dir` 中的套接字路径
+    pub fn socket_in(dir: &Path, pid: u32, name: &str) -> PathBuf {
+        dir.join(format!("{}.{}.sock", pid, name))
+    }
+
+    /// 将记录写入目录 `dir`，返回记录文件的路径。
+    ///
+    /// 先写入临时文件再重命名，读取方不会读到不完整的记录。目录可能是所有用户共享的，
+    /// 临时文件以 `O_EXCL` 新建，不会跟随其他用户放置的符号链接。
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, String> {
+        let path = DiscoveryRecord::path_in(dir, self.pid, &self.name);
+        let tmp = path.with_extension("tmp");
+        let content = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        if is_own_file(&tmp) {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o644)
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|err| format!("write {} failed: {}", path.display(), err))?;
+        Ok(path)
+    }
+
+    /// 读取目录 `dir` 中按进程 id 和实例名称命名的记录文件，返回记录和文件所有者的用户 id，
+    /// 不检查记录是否可信。
+    fn read_dir(dir: &Path) -> Vec<(DiscoveryRecord, u32)> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == RECORD_EXTENSION))
+            .filter_map(|path| {
+                let mut file = open_regular(&path)?;
+                let owner = file.metadata().ok()?.uid();
+                let mut content = String::new();
+                file.read_to_string(&mut content).ok()?;
+                let record: DiscoveryRecord = serde_json::from_str(&content).ok()?;
+                (is_valid_instance_name(&record.name)
+                    && path == DiscoveryRecord::path_in(dir, record.pid, &record.name))
+                .then_some((record, owner))
+            })
+            .collect()
+    }
+
+    /// 读取目录 `dir` 中的记录，包括已经失效的记录，无法解析或不可信的记录被忽略。
+    pub fn scan(dir: &Path) -> Vec<DiscoveryRecord> {
+        DiscoveryRecord::scan_in(Path::new("/"), dir, process_owner)
+    }
+
+    /// 通过根目录 `root` 读取目录 `dir` 中的记录，用于读取其他挂载命名空间中的记录，
+    /// `dir` 是服务端看到的路径。
+    ///
+    /// `owner` 返回记录中的进程 id 对应的进程的用户 id，记录文件的所有者与之不同的记录被忽略。
+    pub fn scan_in(
+        root: &Path,
+        dir: &Path,
+        owner: impl Fn(u32) -> Option<u32>,
+    ) -> Vec<DiscoveryRecord> {
+        DiscoveryRecord::read_dir(&root.join(dir.strip_prefix("/").unwrap_or(dir)))
+            .into_iter()
+            .filter(|(record, uid)| owner(record.pid) == Some(*uid))
+            .filter(|(record, _)| {
+                record.custom_socket
+                    || record.socket == DiscoveryRecord::socket_in(dir, record.pid, &record.name)
+            })
+            .map(|(record, _)| record)
+            .collect()
+    }
+
+    /// 读取所有查找目录中的记录，包括已经失效的记录。
+    pub fn scan_all() -> Vec<DiscoveryRecord> {
+        search_dirs()
+            .iter()
+            .flat_map(|dir| DiscoveryRecord::scan(dir))
+            .collect()
+    }
+
+    /// 服务端是否仍在运行，即套接字的锁文件是否被锁定。
+    pub fn is_alive(&self) -> bool {
+        is_locked(&lock_path(&self.socket))
+    }
+
+    /// 查找进程 `pid` 中名称为 `name` 的实例，不指定名称时进程中只能有一个实例。
+    pub fn resolve(pid: u32, name: Option<&str>) -> Result<DiscoveryRecord, String> {
+        let mut alive: Vec<DiscoveryRecord> = DiscoveryRecord::scan_all()
+            .into_iter()
+            .filter(|record| record.pid == pid)
+            .filter(|record| name.is_none_or(|name| record.name == name))
+            .filter(|record| record.is_alive())
+            .collect();
+        let instance = match name {
+            Some(name) => format!("instance `{}` of process {}", name, pid),
+            None => format!("process {}", pid),
+        };
+        // 进程退出后记录无法通过所有者检查，失效的记录只用于给出原因
+        let stale = search_dirs()
+            .iter()
+            .flat_map(|dir| DiscoveryRecord::read_dir(dir))
+            .any(|(record, _)| record.pid == pid && name.is_none_or(|name| record.name == name));
+        match (alive.len(), stale) {
+            (1, _) => Ok(alive.remove(0)),
+            (0, true) => Err(format!(
+                "{} is stale, the process that created it is no longer serving",
+                instance
+            )),
+            (0, false) => Err(format!(
+                "{} does not serve shell commands, no discovery record found in {}",
+                instance,
+                search_dirs()
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )),
+            _ => Err(format!(
+                "process {} has multiple shell instances: {}",
+                pid,
+                alive
+                    .iter()
+                    .map(|record| record.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// 删除目录 `dir` 中已经失效的记录，以及目录中该实例的套接字和锁文件。
+    ///
+    /// 目录可能是所有用户共享的，记录中的套接字路径不可信，只检查和删除目录中按进程 id 和实例名称
+    /// 命名、由当前用户所有的普通文件和套接字，不跟随符号链接。指定了套接字路径的记录在进程退出后删除。
+    pub fn remove_stale(dir: &Path) {
+        for (record, _) in DiscoveryRecord::read_dir(dir) {
+            let socket = DiscoveryRecord::socket_in(dir, record.pid, &record.name);
+            let lock = lock_path(&socket);
+            let alive = match record.custom_socket {
+                true => process_exists(record.pid),
+                false => is_locked(&lock),
+            };
+            if alive {
+                continue;
+            }
+            for path in [
+                DiscoveryRecord::path_in(dir, record.pid, &record.name),
+                socket,
+                lock,
+            ] {
+                if is_own_file(&path) {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! 握手信息，连接建立后客户端和服务端首先交换 [`Hello`]，确认双方的协议版本兼容，
+//! 并根据对方支持的功能决定可以使用哪些功能。
+
+use serde::{Deserialize, Serialize};
+
+/// 协议版本，消息的格式或交互方式不兼容时增加
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 可选的功能
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// 以 JSON 格式返回命令的返回值
+    Json,
+
+    /// 取消正在执行的命令
+    Cancel,
+
+    /// 命令执行过程中的输出实时发送
+    Streaming,
+
+    /// 同一个套接字上支持 JSON-RPC 2.0
+    ///
+    /// 客户端在握手信息中声明该功能且服务端支持时，握手后连接切换为 JSON-RPC 2.0，
+    /// 每行一个请求，服务端不再发送命令列表。不进行握手、第一个字节为 `{` 或 `[` 的连接直接使用 JSON-RPC 2.0。
+    JsonRpc,
+
+    /// 支持 `.banner on|off` 指令，关闭后执行命令时不输出开始和结束标记
+    Banner,
+
+    /// 对方支持但当前版本不认识的功能
+    #[serde(other)]
+    Unknown,
+}
+
+/// 握手信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    /// 协议版本
+    pub protocol_version: u32,
+
+    /// 发送方使用的 shell_core 的版本
+    pub core_version: String,
+
+    /// 发送方的名称和版本，如 `shell_server 0.1.1`
+    pub version: String,
+
+    /// 发送方的进程名称
+    pub process_name: String,
+
+    /// 发送方的进程 id
+    pub pid: u32,
+
+    /// 发送方支持的功能
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+impl Hello {
+    /// 创建当前进程的握手信息，`version` 为发送方的名称和版本。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use shell_core::{Capability, Hello};
+    ///
+    /// let server = Hello::new("shell_server 0.1.1", &[Capability::Json]);
+    /// let client = Hello::new("shell_client 0.1.0", &[]);
+    ///
+    /// assert!(client.check(&server).is_ok());
+    /// assert!(server.supports(Capability::Json));
+    /// assert!(!server.supports(Capability::Cancel));
+    /// ```
+    pub fn new(version: &str, capabilities: &[Capability]) -> Hello {
+        Hello {
+            protocol_version: PROTOCOL_VERSION,
+            core_version: env!("CARGO_PKG_VERSION").to_owned(),
+            version: version.to_owned(),
+            process_name: std::env::current_exe()
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                .unwrap_or_default(),
+            pid: std::process::id(),
+            capabilities: capabilities.to_vec(),
+        }
+    }
+
+    /// 对方是否支持某个功能。
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// 检查对方的协议版本是否与自己兼容。
+    pub fn check(&self, peer: &Hello) -> Result<(), String> {
+        if self.protocol_version != peer.protocol_version {
+            return Err(format!(
+                "protocol version mismatch: {} uses protocol {}, {} uses protocol {}",
+                self.version, self.protocol_version, peer.version, peer.protocol_version
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,24 +1,87 @@
+mod discovery;
+mod hello;
+mod meta;
+mod protocol;
+
+pub use discovery::*;
+pub use hello::*;
+pub use meta::*;
+pub use protocol::*;
+
 use std::{
     fmt::Display,
     io::{Read, Write},
 };
 
-#[derive(Debug)]
+/// 一个参数
+///
+/// 数字同时保存解析后的值和原始文本，如 `0x10` 解析为 `Int(16, "0x10")`，
+/// 字符串类型的参数接收原始文本，而不是转换后再格式化的数字。
+#[derive(Debug, Clone, PartialEq)]
 pub enum Argument {
     Str(String),
-    Int(i64),
+    /// 整数及其原始文本
+    Int(i64, String),
+    /// 超出 `i64::MAX` 的无符号整数及其原始文本
+    UInt(u64, String),
+    /// 浮点数及其原始文本
+    Float(f64, String),
+    Bool(bool),
+    /// 字节串，如 `b"\x00\xff"`
+    Bytes(Vec<u8>),
+    /// 列表，如 `[1,2]`，用于向 `Vec<T>` 类型的参数按名称传递多个值
+    List(Vec<Argument>),
+}
+
+impl Argument {
+    /// 参数的类型名称，用于错误信息
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Argument::Str(_) => "string",
+            Argument::Int(..) | Argument::UInt(..) => "integer",
+            Argument::Float(..) => "float",
+            Argument::Bool(_) => "bool",
+            Argument::Bytes(_) => "bytes",
+            Argument::List(_) => "list",
+        }
+    }
 }
 
+/// 数字输出原始文本
 impl Display for Argument {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Argument::Str(s) => write!(f, "{}", s),
-            Argument::Int(i) => write!(f, "{}", i),
+            Argument::Int(_, raw) | Argument::UInt(_, raw) | Argument::Float(_, raw) => {
+                write!(f, "{}", raw)
+            }
+            Argument::Bool(b) => write!(f, "{}", b),
+            Argument::Bytes(b) => write!(f, "b\"{}\"", b.escape_ascii()),
+            Argument::List(items) => write!(
+                f,
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
         }
     }
 }
 
+/// 命令行中的一个参数，`name=value` 形式的参数带有参数名称。
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedArgument {
+    /// 参数名称，按位置传递的参数为 `None`
+    pub name: Option<String>,
+
+    /// 参数值
+    pub value: Argument,
+}
+
 /// 实现一个函数，从提供的读取器中读取一行文本。
+#[deprecated(note = "use `MessageReader` instead")]
 pub fn read_line<T: Read>(conn: &mut T) -> Result<String, String> {
     // 用于存储读取的数据的缓冲区。
     let mut buf = vec![];
@@ -39,7 +102,7 @@ pub fn read_line<T: Read>(conn: &mut T) -> Result<String, String> {
         buf.extend_from_slice(&tmp_buf[0..sz]);
 
         // 如果缓冲区中包含换行符，表示一行文本已读取完成。
-        if buf.ends_with(&[b'\n']) {
+        if buf.ends_with(b"\n") {
             break;
         }
     }
@@ -52,6 +115,7 @@ pub fn read_line<T: Read>(conn: &mut T) -> Result<String, String> {
 }
 
 /// 实现一个函数，将一行文本写入提供的写入器中。
+#[deprecated(note = "use `write_message` instead")]
 pub fn write_line<T: Write>(conn: &mut T, line: &String) -> Result<(), String> {
     // 将一行文本添加到缓冲区中，并在末尾添加换行符。
     let data_to_write = line.to_owned() + "\n";
@@ -61,12 +125,262 @@ pub fn write_line<T: Write>(conn: &mut T, line: &String) -> Result<(), String> {
         .map_err(|err| err.to_string())
 }
 
+/// 解析整数字面量，支持 `0x`、`0o`、`0b` 前缀和负号，超出 `i64::MAX` 的值解析为 [`Argument::UInt`]。
+fn parse_integer(s: &str) -> Option<Argument> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (16, &digits[2..]),
+        Some("0o") | Some("0O") => (8, &digits[2..]),
+        Some("0b") | Some("0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    // 拒绝 from_str_radix 接受的符号，符号已经在上面处理过了
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = u64::from_str_radix(digits, radix).ok()?;
+    if negative {
+        if value <= i64::MAX as u64 + 1 {
+            Some(Argument::Int((value as i64).wrapping_neg(), s.to_owned()))
+        } else {
+            None
+        }
+    } else if let Ok(i) = i64::try_from(value) {
+        Some(Argument::Int(i, s.to_owned()))
+    } else {
+        Some(Argument::UInt(value, s.to_owned()))
+    }
+}
+
+/// 解析浮点数字面量，只接受以数字、符号或小数点开头的文本，`inf`、`nan` 等作为字符串处理。
+///
+/// 超出整数范围的整数字面量不解析为有精度损失的浮点数，与超出范围的十六进制等字面量一样作为字符串处理，
+/// 超出浮点数范围的字面量（如 `1e400`）同样作为字符串处理。
+fn parse_float(s: &str) -> Option<Argument> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits = digits.strip_prefix('.').unwrap_or(digits);
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse::<f64>()
+        .ok()
+        .filter(|f| f.is_finite())
+        .map(|f| Argument::Float(f, s.to_owned()))
+}
+
+/// 处理引号中的转义字符，支持 `\n`、`\t`、`\r`、`\0` 和 `\xNN`，其他字符原样保留。
+fn unescape_quoted(s: &str) -> Vec<u8> {
+    let mut result = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push(b'\n'),
+            Some('t') => result.push(b'\t'),
+            Some('r') => result.push(b'\r'),
+            Some('0') => result.push(0),
+            Some('x') => {
+                let hex: String = chars.clone().take(2).collect();
+                match hex.len() == 2 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    true => {
+                        result.push(u8::from_str_radix(&hex, 16).unwrap_or_default());
+                        chars.nth(1);
+                    }
+                    // 不是两位十六进制数字时原样保留
+                    false => result.extend_from_slice(b"\\x"),
+                }
+            }
+            Some(other) => {
+                let mut buf = [0u8; 4];
+                result.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+            None => result.push(b'\\'),
+        }
+    }
+    result
+}
+
+/// 处理未加引号的参数：反斜杠转义下一个字符，未转义的引号被丢弃。
+fn unescape_plain(s: &str) -> String {
+    let mut result = String::new();
+    let mut escape = false;
+    for c in s.chars() {
+        if escape {
+            result.push(c);
+            escape = false;
+        } else {
+            match c {
+                '\\' => escape = true,
+                '"' => {}
+                _ => result.push(c),
+            }
+        }
+    }
+    result
+}
+
+/// 将一个未经处理的参数文本转换为参数。
+fn parse_argument(raw: &str) -> Argument {
+    let trimmed = raw.trim();
+
+    // 字节串 b"..."
+    if let Some(inner) = trimmed
+        .strip_prefix("b\"")
+        .and_then(|s| s.strip_suffix('"'))
+    {
+        return Argument::Bytes(unescape_quoted(inner));
+    }
+
+    // 加引号的参数总是作为字符串处理
+    if let Some(inner) = trimmed.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        let bytes = unescape_quoted(inner);
+        return match String::from_utf8(bytes) {
+            Ok(s) => Argument::Str(s),
+            Err(err) => Argument::Bytes(err.into_bytes()),
+        };
+    }
+
+    if let Some(inner) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        return Argument::List(parse_arguments(inner));
+    }
+
+    match trimmed {
+        "true" => return Argument::Bool(true),
+        "false" => return Argument::Bool(false),
+        _ => {}
+    }
+
+    parse_integer(trimmed)
+        .or_else(|| parse_float(trimmed))
+        .unwrap_or_else(|| Argument::Str(unescape_plain(raw)))
+}
+
 /// 实现一个函数，将一段文本解析为一组参数。
+///
+/// 参数之间以逗号分隔，支持以下格式：
+///
+/// - 整数：`42`、`-7`、`0x1F`、`0o17`、`0b101`，超出 `i64::MAX` 的值解析为 [`Argument::UInt`]，
+///   超出 `u64::MAX` 或小于 `i64::MIN` 的值作为字符串处理
+/// - 浮点数：`1.5`、`-2e3`
+/// - 布尔值：`true`、`false`
+/// - 字符串：`"a,b"` 或未加引号的文本
+/// - 字节串：`b"\x00\xff"`
+/// - 列表：`[1,2]`，列表中的逗号不分隔参数
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_core::{parse_arguments, Argument};
+///
+/// assert_eq!(
+///     parse_arguments(r#"0x1F,1.5,true,"7",b"\x00\xff""#),
+///     vec![
+///         Argument::Int(31, "0x1F".to_owned()),
+///         Argument::Float(1.5, "1.5".to_owned()),
+///         Argument::Bool(true),
+///         Argument::Str("7".to_owned()),
+///         Argument::Bytes(vec![0, 255]),
+///     ]
+/// );
+/// assert_eq!(
+///     parse_arguments(r#"18446744073709551616,0x10000000000000000,1e400,"\x4""#),
+///     vec![
+///         Argument::Str("18446744073709551616".to_owned()),
+///         Argument::Str("0x10000000000000000".to_owned()),
+///         Argument::Str("1e400".to_owned()),
+///         Argument::Str(r"\x4".to_owned()),
+///     ]
+/// );
+/// ```
 pub fn parse_arguments(input: &str) -> Vec<Argument> {
-    // 用于存储解析出的参数的向量。
+    split_arguments(input)
+        .iter()
+        .map(|raw| parse_argument(raw))
+        .collect()
+}
+
+/// 从参数文本中拆分出 `name=` 形式的参数名称，名称只能包含字母、数字和下划线，且不能以数字开头。
+fn split_name(raw: &str) -> Option<(&str, &str)> {
+    let (name, value) = raw.trim_start().split_once('=')?;
+    let name = name.trim_end();
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some((name, value))
+}
+
+/// 将一段文本解析为一组参数，支持 `name=value` 形式的命名参数。
+///
+/// 参数值的格式与 [`parse_arguments`] 相同，值中包含 `=` 的字符串参数需要加引号或写作 `a\=b`，
+/// 向 `Vec<T>` 类型的参数按名称传递多个值时使用列表，如 `weights=[1,2]`。
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_core::{parse_named_arguments, Argument, NamedArgument};
+///
+/// assert_eq!(
+///     parse_named_arguments(r#"1,ratio=0.5,"a=b""#),
+///     vec![
+///         NamedArgument {
+///             name: None,
+///             value: Argument::Int(1, "1".to_owned()),
+///         },
+///         NamedArgument {
+///             name: Some("ratio".to_owned()),
+///             value: Argument::Float(0.5, "0.5".to_owned()),
+///         },
+///         NamedArgument {
+///             name: None,
+///             value: Argument::Str("a=b".to_owned()),
+///         },
+///     ]
+/// );
+/// assert_eq!(
+///     parse_named_arguments("weights=[1,2]")[0].value,
+///     Argument::List(vec![
+///         Argument::Int(1, "1".to_owned()),
+///         Argument::Int(2, "2".to_owned()),
+///     ])
+/// );
+/// ```
+pub fn parse_named_arguments(input: &str) -> Vec<NamedArgument> {
+    split_arguments(input)
+        .iter()
+        .map(|raw| match split_name(raw) {
+            Some((name, value)) => NamedArgument {
+                name: Some(name.to_owned()),
+                value: parse_argument(value),
+            },
+            None => NamedArgument {
+                name: None,
+                value: parse_argument(raw),
+            },
+        })
+        .collect()
+}
+
+/// 已经读取的参数文本是否为空或只有参数名称，即下一个字符是否为参数值的开头。
+fn is_value_start(current: &str) -> bool {
+    current.trim().is_empty()
+        || split_name(current).is_some_and(|(_, value)| value.trim().is_empty())
+}
+
+/// 将一段文本按逗号拆分为参数的原始文本，保留引号和反斜杠。
+fn split_arguments(input: &str) -> Vec<String> {
+    // 用于存储拆分出的参数原始文本的向量。
     let mut result = Vec::new();
 
-    // 用于存储当前正在解析的参数的字符串。
+    // 用于存储当前正在解析的参数的原始文本，保留引号和反斜杠。
     let mut current_arg = String::new();
 
     // 用于指示是否在引号中。
@@ -75,59 +389,56 @@ pub fn parse_arguments(input: &str) -> Vec<Argument> {
     // 用于指示是否需要转义。
     let mut escape = false;
 
+    // 用于指示是否在列表中，只有参数值开头的 `[` 开始一个列表。
+    let mut in_list = false;
+
     // 迭代输入文本中的字符
     for c in input.chars() {
         // 如果需要转义，直接将字符添加到当前参数中并跳过转义处理
         if escape {
             current_arg.push(c);
             escape = false;
-        } else {
-            match c {
-                // 如果遇到引号，切换 in_quotes 状态
-                '"' => in_quotes = !in_quotes,
+            continue;
+        }
+        match c {
+            // 如果遇到引号，切换 in_quotes 状态
+            '"' => {
+                in_quotes = !in_quotes;
+                current_arg.push(c);
+            }
 
-                // 如果遇到反斜杠，需要转义下一个字符
-                '\\' => escape = true,
+            // 如果遇到反斜杠，需要转义下一个字符
+            '\\' => {
+                escape = true;
+                current_arg.push(c);
+            }
 
-                // 如果遇到逗号且不在引号中，表示一个参数结束
-                ',' if !in_quotes => {
-                    // 如果参数以引号开始和结束，移除引号并作为字符串处理
-                    if current_arg.starts_with('"') && current_arg.ends_with('"') {
-                        current_arg.remove(current_arg.len() - 1);
-                        current_arg.remove(0);
-                        result.push(Argument::Str(current_arg.clone()));
-                    } else {
-                        // 尝试将当前参数解析为整数
-                        if let Ok(num) = current_arg.trim().parse::<i64>() {
-                            result.push(Argument::Int(num));
-                        } else {
-                            // 如果无法将参数解析为整数，则作为字符串处理
-                            result.push(Argument::Str(current_arg.clone()));
-                        }
-                    }
-                    // 清空当前参数
-                    current_arg.clear();
-                }
-                // 其他字符直接添加到当前参数中
-                _ => current_arg.push(c),
+            '[' if !in_quotes && !in_list && is_value_start(&current_arg) => {
+                in_list = true;
+                current_arg.push(c);
             }
+
+            ']' if !in_quotes && in_list => {
+                in_list = false;
+                current_arg.push(c);
+            }
+
+            // 如果遇到逗号且不在引号和列表中，表示一个参数结束
+            ',' if !in_quotes && !in_list => {
+                result.push(std::mem::take(&mut current_arg));
+            }
+
+            // 其他字符直接添加到当前参数中
+            _ => current_arg.push(c),
         }
     }
 
     // 处理最后一个参数
     if !current_arg.is_empty() {
-        if current_arg.starts_with('"') && current_arg.ends_with('"') {
-            current_arg.remove(current_arg.len() - 1);
-            current_arg.remove(0);
-            result.push(Argument::Str(current_arg));
-        } else if let Ok(num) = current_arg.parse::<i64>() {
-            result.push(Argument::Int(num));
-        } else {
-            result.push(Argument::Str(current_arg));
-        }
+        result.push(current_arg);
     }
 
-    // 返回解析出的参数列表
+    // 返回拆分出的参数列表
     result
 }
 
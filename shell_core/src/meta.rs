@@ -0,0 +1,158 @@
+//! 命令的元信息，服务端在连接建立时发送给客户端，用于帮助信息和自动完成。
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// 命令的参数信息
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParamMeta {
+    /// 参数名称
+    pub name: String,
+
+    /// 参数类型
+    pub ty: String,
+
+    /// 默认值，省略该参数时使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+
+    /// 参数是否可以省略，如 `Option<T>` 省略时为 `None`，`Vec<T>` 省略时为空
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+impl Display for ParamMeta {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.default {
+            Some(default) => write!(f, "{}: {} = {}", self.name, self.ty, default),
+            None => write!(f, "{}: {}", self.name, self.ty),
+        }
+    }
+}
+
+/// 命令的元信息
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandMeta {
+    /// 命令名称
+    pub name: String,
+
+    /// 帮助信息
+    pub help: String,
+
+    /// 参数列表
+    pub params: Vec<ParamMeta>,
+
+    /// 返回值类型
+    pub ret: String,
+
+    /// 使用示例
+    #[serde(default)]
+    pub examples: Vec<String>,
+}
+
+impl CommandMeta {
+    /// 设置帮助信息。
+    pub fn help(&mut self, help: &str) -> &mut CommandMeta {
+        self.help = help.to_owned();
+        self
+    }
+
+    /// 按顺序设置参数名称，多余的名称会被忽略。
+    pub fn param_names(&mut self, names: &[&str]) -> &mut CommandMeta {
+        for (param, name) in self.params.iter_mut().zip(names) {
+            param.name = name.to_string();
+        }
+        self
+    }
+
+    /// 设置参数的名称和类型，会覆盖已有的参数列表，同一位置的参数是否可以省略保持不变。
+    pub fn params(&mut self, params: &[(&str, &str)]) -> &mut CommandMeta {
+        self.params = params
+            .iter()
+            .enumerate()
+            .map(|(index, (name, ty))| ParamMeta {
+                name: name.to_string(),
+                ty: ty.to_string(),
+                default: None,
+                optional: self.params.get(index).is_some_and(|p| p.optional),
+            })
+            .collect();
+        self
+    }
+
+    /// 将指定的参数设置为可以省略，用于参数类型未知的命令，不存在的参数会被忽略。
+    pub fn optional(&mut self, names: &[&str]) -> &mut CommandMeta {
+        for param in self.params.iter_mut() {
+            if names.contains(&param.name.as_str()) {
+                param.optional = true;
+            }
+        }
+        self
+    }
+
+    /// 设置参数的默认值，默认值的格式与命令行中的参数相同，不存在的参数会被忽略。
+    pub fn defaults(&mut self, defaults: &[(&str, &str)]) -> &mut CommandMeta {
+        for (name, value) in defaults {
+            if let Some(param) = self.params.iter_mut().find(|p| p.name == *name) {
+                param.default = Some(value.to_string());
+            }
+        }
+        self
+    }
+
+    /// 设置返回值类型。
+    pub fn ret(&mut self, ret: &str) -> &mut CommandMeta {
+        self.ret = ret.to_owned();
+        self
+    }
+
+    /// 添加一个使用示例。
+    pub fn example(&mut self, example: &str) -> &mut CommandMeta {
+        self.examples.push(example.to_owned());
+        self
+    }
+
+    /// 生成命令签名，如 `add_two(a: i64, b: i64 = 1) -> i64`，返回值为 `()` 时省略。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use shell_core::CommandMeta;
+    ///
+    /// let mut meta = CommandMeta {
+    ///     name: "add_two".to_owned(),
+    ///     ..Default::default()
+    /// };
+    /// meta.params(&[("a", "i64"), ("b", "i64")])
+    ///     .defaults(&[("b", "1")])
+    ///     .ret("i64");
+    /// assert_eq!(meta.signature(), "add_two(a: i64, b: i64 = 1) -> i64");
+    /// ```
+    pub fn signature(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        match self.ret.as_str() {
+            "" | "()" => format!("{}({})", self.name, params),
+            ret => format!("{}({}) -> {}", self.name, params, ret),
+        }
+    }
+
+    /// 帮助信息的第一行，用于命令列表。
+    pub fn summary(&self) -> &str {
+        self.help.lines().next().unwrap_or_default()
+    }
+}
+
+/// 将命令列表编码为一行文本。
+pub fn encode_command_list(commands: &[CommandMeta]) -> Result<String, String> {
+    serde_json::to_string(commands).map_err(|err| err.to_string())
+}
+
+/// 从一行文本中解码命令列表。
+pub fn decode_command_list(line: &str) -> Result<Vec<CommandMeta>, String> {
+    serde_json::from_str(line).map_err(|err| err.to_string())
+}
@@ -0,0 +1,348 @@
+//! 客户端与服务端之间的消息协议
+//!
+//! 每条消息编码为一帧：4 字节大端序的长度，之后是 1 字节的消息类型和消息内容，
+//! 长度包含消息类型和消息内容。
+//!
+//! 与一次命令调用相关的消息以 8 字节大端序的请求 id 开头，客户端据此将输出和执行结果
+//! 与发出的命令对应起来。
+//!
+//! 连接建立后双方首先互相发送 [`Message::Hello`]，协议版本兼容时服务端再发送
+//! [`Message::CommandList`]，不兼容时服务端发送 [`Message::Error`] 并关闭连接。
+//! 帧的长度不超过 [`MAX_FRAME_SIZE`]，第一个字节总是 0 或 1，服务端据此区分 JSON-RPC 连接。
+//!
+//! ```text
+//! +----------------+------+-----------------+
+//! | length: u32 BE | type | payload ...     |
+//! +----------------+------+-----------------+
+//! ```
+
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+use crate::{
+    hello::Hello,
+    meta::{decode_command_list, encode_command_list, CommandMeta},
+};
+
+/// 单帧的默认最大长度
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// 帧头中长度字段的字节数
+const LENGTH_SIZE: usize = 4;
+
+/// 命令的输出流
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// 客户端与服务端之间传递的消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// 握手，连接建立后双方首先发送，内容为 JSON
+    Hello(Hello),
+
+    /// 服务端中可用的命令列表
+    CommandList(Vec<CommandMeta>),
+
+    /// 执行一条命令行，`id` 由客户端分配
+    Invoke { id: u64, line: String },
+
+    /// 命令执行过程中的输出
+    Output {
+        id: u64,
+        stream: Stream,
+        data: Vec<u8>,
+    },
+
+    /// 命令执行成功，`value` 为返回值的文本，没有返回值时为空
+    Result {
+        id: u64,
+        value: String,
+        duration: Duration,
+    },
+
+    /// 命令执行失败，与请求无关的错误 `id` 为 0
+    Error {
+        id: u64,
+        message: String,
+        duration: Duration,
+    },
+
+    /// 取消正在执行的命令
+    Cancel { id: u64 },
+
+    /// 心跳，收到后原样回复
+    Ping,
+}
+
+impl Message {
+    fn type_id(&self) -> u8 {
+        match self {
+            Message::Hello(_) => 1,
+            Message::CommandList(_) => 2,
+            Message::Invoke { .. } => 3,
+            Message::Output { .. } => 4,
+            Message::Result { .. } => 5,
+            Message::Error { .. } => 6,
+            Message::Cancel { .. } => 7,
+            Message::Ping => 8,
+        }
+    }
+
+    /// 将消息编码为一帧。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use shell_core::{FrameDecoder, Message};
+    ///
+    /// let mut decoder = FrameDecoder::default();
+    /// let invoke = Message::Invoke {
+    ///     id: 1,
+    ///     line: "add_two 1,2".to_owned(),
+    /// };
+    /// let frame = invoke.encode().unwrap();
+    ///
+    /// // 分两次收到一帧，以及紧随其后的另一帧
+    /// decoder.feed(&frame[..3]);
+    /// assert_eq!(decoder.decode().unwrap(), None);
+    /// decoder.feed(&frame[3..]);
+    /// decoder.feed(&Message::Ping.encode().unwrap());
+    ///
+    /// assert_eq!(decoder.decode().unwrap(), Some(invoke));
+    /// assert_eq!(decoder.decode().unwrap(), Some(Message::Ping));
+    /// assert_eq!(decoder.decode().unwrap(), None);
+    /// ```
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut payload = vec![];
+        match self {
+            Message::Hello(hello) => payload.extend_from_slice(
+                serde_json::to_string(hello)
+                    .map_err(|err| err.to_string())?
+                    .as_bytes(),
+            ),
+            Message::CommandList(commands) => {
+                payload.extend_from_slice(encode_command_list(commands)?.as_bytes())
+            }
+            Message::Invoke { id, line } => {
+                payload.extend_from_slice(&id.to_be_bytes());
+                payload.extend_from_slice(line.as_bytes());
+            }
+            Message::Output { id, stream, data } => {
+                payload.extend_from_slice(&id.to_be_bytes());
+                payload.push(match stream {
+                    Stream::Stdout => 1,
+                    Stream::Stderr => 2,
+                });
+                payload.extend_from_slice(data);
+            }
+            Message::Result {
+                id,
+                value: text,
+                duration,
+            }
+            | Message::Error {
+                id,
+                message: text,
+                duration,
+            } => {
+                payload.extend_from_slice(&id.to_be_bytes());
+                payload.extend_from_slice(&(duration.as_micros() as u64).to_be_bytes());
+                payload.extend_from_slice(text.as_bytes());
+            }
+            Message::Cancel { id } => payload.extend_from_slice(&id.to_be_bytes()),
+            Message::Ping => {}
+        }
+        let length = payload.len() + 1;
+        if length > MAX_FRAME_SIZE {
+            return Err(format!("frame too large: {} bytes", length));
+        }
+
+        let mut frame = Vec::with_capacity(LENGTH_SIZE + length);
+        frame.extend_from_slice(&(length as u32).to_be_bytes());
+        frame.push(self.type_id());
+        frame.append(&mut payload);
+        Ok(frame)
+    }
+
+    /// 从一帧的消息类型和消息内容中解码消息。
+    fn decode(type_id: u8, payload: &[u8]) -> Result<Message, String> {
+        let mut payload = Payload(payload);
+        Ok(match type_id {
+            1 => Message::Hello(
+                serde_json::from_str(&payload.text()?).map_err(|err| err.to_string())?,
+            ),
+            2 => Message::CommandList(decode_command_list(&payload.text()?)?),
+            3 => Message::Invoke {
+                id: payload.u64()?,
+                line: payload.text()?,
+            },
+            4 => Message::Output {
+                id: payload.u64()?,
+                stream: match payload.u8()? {
+                    1 => Stream::Stdout,
+                    2 => Stream::Stderr,
+                    other => return Err(format!("unknown output stream: {}", other)),
+                },
+                data: payload.0.to_vec(),
+            },
+            5 => Message::Result {
+                id: payload.u64()?,
+                duration: Duration::from_micros(payload.u64()?),
+                value: payload.text()?,
+            },
+            6 => Message::Error {
+                id: payload.u64()?,
+                duration: Duration::from_micros(payload.u64()?),
+                message: payload.text()?,
+            },
+            7 => Message::Cancel { id: payload.u64()? },
+            8 => Message::Ping,
+            other => return Err(format!("unknown message type: {}", other)),
+        })
+    }
+
+    /// 与请求无关的错误，如收到了无法处理的消息。
+    pub fn error(message: String) -> Message {
+        Message::Error {
+            id: 0,
+            message,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// 按顺序读取消息内容中的字段
+struct Payload<'a>(&'a [u8]);
+
+impl Payload<'_> {
+    fn u8(&mut self) -> Result<u8, String> {
+        let (&value, rest) = self.0.split_first().ok_or("truncated message")?;
+        self.0 = rest;
+        Ok(value)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let (value, rest) = self.0.split_first_chunk().ok_or("truncated message")?;
+        self.0 = rest;
+        Ok(u64::from_be_bytes(*value))
+    }
+
+    /// 剩余的全部内容作为文本
+    fn text(&mut self) -> Result<String, String> {
+        let text = String::from_utf8(self.0.to_vec()).map_err(|err| err.to_string())?;
+        self.0 = &[];
+        Ok(text)
+    }
+}
+
+/// 帧解码器，缓存不完整的帧，一次收到的数据中可以包含多帧。
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        FrameDecoder::new(MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameDecoder {
+    /// 创建一个解码器，超过 `max_frame_size` 的帧会被拒绝。
+    pub fn new(max_frame_size: usize) -> FrameDecoder {
+        FrameDecoder {
+            buf: vec![],
+            max_frame_size,
+        }
+    }
+
+    /// 添加收到的数据。
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// 取出一条完整的消息，数据不足一帧时返回 `None`。
+    pub fn decode(&mut self) -> Result<Option<Message>, String> {
+        let header = match self.buf.get(..LENGTH_SIZE) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let length = u32::from_be_bytes(header.try_into().map_err(|_| "invalid frame header")?);
+        let length = length as usize;
+        if length == 0 {
+            return Err("empty frame".to_owned());
+        }
+        if length > self.max_frame_size {
+            return Err(format!(
+                "frame too large: {} bytes, max {} bytes",
+                length, self.max_frame_size
+            ));
+        }
+        if self.buf.len() < LENGTH_SIZE + length {
+            return Ok(None);
+        }
+
+        let frame: Vec<u8> = self.buf.drain(..LENGTH_SIZE + length).collect();
+        Message::decode(frame[LENGTH_SIZE], &frame[LENGTH_SIZE + 1..]).map(Some)
+    }
+}
+
+/// 从读取器中读取消息，读取的数据中多余的部分会保留给下一条消息。
+pub struct MessageReader<R> {
+    inner: R,
+    decoder: FrameDecoder,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub fn new(inner: R) -> MessageReader<R> {
+        MessageReader {
+            inner,
+            decoder: FrameDecoder::default(),
+        }
+    }
+
+    /// 读取一条消息，连接关闭时返回错误。
+    pub fn read(&mut self) -> Result<Message, String> {
+        self.try_read()?
+            .ok_or_else(|| "connection closed".to_string())
+    }
+
+    /// 读取一条消息，连接在两条消息之间关闭时返回 `None`，在一条消息中间关闭时返回错误。
+    pub fn try_read(&mut self) -> Result<Option<Message>, String> {
+        let mut buf = [0u8; 4096];
+        loop {
+            if let Some(message) = self.decoder.decode()? {
+                return Ok(Some(message));
+            }
+            let sz = self.inner.read(&mut buf).map_err(|err| err.to_string())?;
+            if sz == 0 {
+                return match self.decoder.buf.is_empty() {
+                    true => Ok(None),
+                    false => Err("connection closed in the middle of a message".to_string()),
+                };
+            }
+            self.decoder.feed(&buf[..sz]);
+        }
+    }
+
+    /// 获取内部的读取器。
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// 取出内部的读取器，以及已经读取但还没有解码的数据，用于在握手后切换到其他协议。
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        (self.inner, self.decoder.buf)
+    }
+}
+
+/// 将一条消息写入写入器中。
+pub fn write_message<W: Write>(conn: &mut W, message: &Message) -> Result<(), String> {
+    conn.write_all(&message.encode()?)
+        .map_err(|err| err.to_string())
+}
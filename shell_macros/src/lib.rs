@@ -0,0 +1,195 @@
+//! shell_server 的过程宏
+//!
+//! `#[shell_command]` 可以标注在任意模块中的函数上，被标注的函数会被收集到
+//! `shell_server::SHELL_COMMANDS` 中，通过 `Shell::with_registered_commands()` 自动注册。
+//!
+//! # 示例
+//!
+//! ```ignore
+//! use shell_server::shell_command;
+//!
+//! /// 两数相加
+//! #[shell_command(name = "add_two", example = "add_two 1,b=2", default(b = "1"))]
+//! fn add(a: i64, b: i64) -> i64 {
+//!     a + b
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse::Parser, parse_macro_input, punctuated::Punctuated, Expr, ExprLit, FnArg, Ident, ItemFn,
+    Lit, LitStr, Meta, MetaNameValue, Pat, ReturnType, Token,
+};
+
+/// 属性宏的参数
+#[derive(Default)]
+struct CommandArgs {
+    /// 命令名称，默认为函数名
+    name: Option<LitStr>,
+
+    /// 帮助信息，默认为函数的文档注释
+    help: Option<LitStr>,
+
+    /// 使用示例，可以出现多次
+    examples: Vec<LitStr>,
+
+    /// 参数的默认值，如 `default(ratio = "1.0")`
+    defaults: Vec<(Ident, LitStr)>,
+}
+
+/// 取出字符串字面量
+fn lit_str(expr: &Expr) -> syn::Result<LitStr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) => Ok(s.clone()),
+        other => Err(syn::Error::new_spanned(other, "expect string literal")),
+    }
+}
+
+impl CommandArgs {
+    fn parse(attr: TokenStream) -> syn::Result<CommandArgs> {
+        let mut args = CommandArgs::default();
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse(attr)?;
+        for meta in metas {
+            let nv = match meta {
+                Meta::NameValue(nv) => nv,
+                Meta::List(list) if list.path.is_ident("default") => {
+                    let defaults = list.parse_args_with(
+                        Punctuated::<MetaNameValue, Token![,]>::parse_terminated,
+                    )?;
+                    for nv in defaults {
+                        let name = nv.path.require_ident()?.clone();
+                        args.defaults.push((name, lit_str(&nv.value)?));
+                    }
+                    continue;
+                }
+                other => return Err(syn::Error::new_spanned(other, "expect `key = \"value\"`")),
+            };
+            let value = lit_str(&nv.value)?;
+            if nv.path.is_ident("name") {
+                args.name = Some(value);
+            } else if nv.path.is_ident("help") {
+                args.help = Some(value);
+            } else if nv.path.is_ident("example") {
+                args.examples.push(value);
+            } else {
+                return Err(syn::Error::new_spanned(nv.path, "unknown argument"));
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// 将类型的 token 转换为便于阅读的字符串，如 `Vec < i64 >` 转换为 `Vec<i64>`。
+fn type_to_string<T: ToTokens>(ty: &T) -> String {
+    ty.to_token_stream()
+        .to_string()
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace("& ", "&")
+        .replace(" ::", "::")
+        .replace(":: ", "::")
+}
+
+/// 提取函数的文档注释作为默认的帮助信息。
+fn doc_of(func: &ItemFn) -> String {
+    func.attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+fn expand(args: CommandArgs, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &func.sig.ident;
+    let name = args
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let help = args
+        .help
+        .unwrap_or_else(|| LitStr::new(&doc_of(&func), Span::call_site()));
+
+    let mut param_names = vec![];
+    let mut param_types = vec![];
+    for (index, input) in func.sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Receiver(recv) => {
+                return Err(syn::Error::new_spanned(
+                    recv,
+                    "shell_command can not be used on methods",
+                ))
+            }
+            FnArg::Typed(pat) => {
+                let param_name = match pat.pat.as_ref() {
+                    Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                    _ => format!("arg{}", index + 1),
+                };
+                param_names.push(param_name.trim_start_matches('_').to_owned());
+                param_types.push(type_to_string(&pat.ty));
+            }
+        }
+    }
+    let ret = match &func.sig.output {
+        ReturnType::Default => "()".to_owned(),
+        ReturnType::Type(_, ty) => type_to_string(ty),
+    };
+
+    for (name, _) in &args.defaults {
+        if !param_names.contains(&name.to_string()) {
+            return Err(syn::Error::new_spanned(name, "unknown parameter"));
+        }
+    }
+    let default_names = args.defaults.iter().map(|(name, _)| name.to_string());
+    let default_values = args.defaults.iter().map(|(_, value)| value);
+
+    let examples = &args.examples;
+    let entry = format_ident!("__SHELL_COMMAND_{}", ident.to_string().to_uppercase());
+
+    Ok(quote! {
+        #func
+
+        #[::shell_server::__private::linkme::distributed_slice(::shell_server::SHELL_COMMANDS)]
+        #[linkme(crate = ::shell_server::__private::linkme)]
+        #[doc(hidden)]
+        static #entry: ::shell_server::CommandEntry = ::shell_server::CommandEntry {
+            name: #name,
+            help: #help,
+            params: &[#((#param_names, #param_types)),*],
+            defaults: &[#((#default_names, #default_values)),*],
+            ret: #ret,
+            examples: &[#(#examples),*],
+            register: |shell| shell.reg_func(#name.to_string(), #ident),
+        };
+    })
+}
+
+/// 将函数注册为 shell 命令。
+///
+/// - `name`: 命令名称，默认为函数名。
+/// - `help`: 帮助信息，默认为函数的文档注释。
+/// - `example`: 使用示例，可以出现多次。
+/// - `default`: 参数的默认值，如 `default(ratio = "1.0", round = "false")`。
+#[proc_macro_attribute]
+pub fn shell_command(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    CommandArgs::parse(attr)
+        .and_then(|args| expand(args, func))
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
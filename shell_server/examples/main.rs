@@ -1,21 +1,28 @@
-use libc::exit;
-use shell_server::{reg_shell_cmd, Server, Shell};
+use serde::Serialize;
+use shell_server::{
+    reg_shell_cmd, shell_command, shell_eprintln, shell_println, JsonReply, Reply, Server,
+    ServerConfig, Shell,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 fn print_hello() {
-    println!("Hello, world!");
+    shell_println!("Hello, world!");
 }
 
 fn add_two(a: i64, b: i64) -> i64 {
-    println!("{} + {} = {}", a, b, a + b);
+    shell_println!("{} + {} = {}", a, b, a + b);
     a + b
 }
 
-fn print_str(s: &String) {
-    println!("{}", s);
+fn print_str(s: String) {
+    shell_println!("{}", s);
 }
 
 fn add_seven(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> i64 {
-    println!(
+    shell_println!(
         "{} + {} + {} + {} + {} + {} + {} = {}",
         a,
         b,
@@ -29,32 +36,147 @@ fn add_seven(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> i64 {
     a + b + c + d + e + f + g
 }
 
+/// 两个字符串拼接
+#[shell_command(name = "concat")]
+fn concat(a: String, b: String) -> String {
+    a + &b
+}
+
+/// 按比例缩放，`round` 为 true 时四舍五入
+#[shell_command(
+    name = "scale",
+    example = "scale 3,0.5,true",
+    example = "scale 3,round=true",
+    default(ratio = "1.0", round = "false")
+)]
+fn scale(value: f64, ratio: f64, round: bool) -> f64 {
+    if round {
+        (value * ratio).round()
+    } else {
+        value * ratio
+    }
+}
+
+/// 统计字节串的长度
+#[shell_command(name = "byte_len")]
+fn byte_len(bytes: Vec<u8>) -> usize {
+    bytes.len()
+}
+
+#[derive(Serialize)]
+struct ProcessInfo {
+    pid: u32,
+    args: Vec<String>,
+}
+
+/// 当前进程的信息
+#[shell_command(name = "process_info")]
+fn process_info() -> JsonReply<ProcessInfo> {
+    JsonReply(ProcessInfo {
+        pid: std::process::id(),
+        args: std::env::args().collect(),
+    })
+}
+
+/// 按分隔符拆分字符串
+#[shell_command(name = "split")]
+fn split(s: String, sep: String) -> Vec<String> {
+    s.split(sep.as_str()).map(|x| x.to_owned()).collect()
+}
+
+/// 设置权重，权重个数不限，如 `set_weights 1,2,3`
+#[shell_command(name = "set_weights", example = "set_weights 0.5,1,2")]
+fn set_weights(weights: Vec<f64>) -> String {
+    format!(
+        "{} weights, sum: {}",
+        weights.len(),
+        weights.iter().fold(0.0, |a, b| a + b)
+    )
+}
+
+/// 打招呼，不指定问候语时使用 Hello
+#[shell_command(name = "greet", example = "greet world", example = "greet world,Hi")]
+fn greet(name: String, greeting: Option<String>) -> String {
+    format!("{}, {}!", greeting.unwrap_or("Hello".to_owned()), name)
+}
+
+/// 整数除法，除数为 0 时返回错误
+#[shell_command(name = "divide", example = "divide 7,2", example = "divide 7,0")]
+fn divide(a: i64, b: i64) -> Result<i64, String> {
+    a.checked_div(b)
+        .ok_or(format!("can not divide {} by zero", a))
+}
+
+/// 取出列表中的第 `index` 个元素，越界时 panic
+#[shell_command(name = "nth", example = "nth 1,1,2,3", example = "nth 5,1,2,3")]
+fn nth(index: usize, values: Vec<i64>) -> i64 {
+    if index >= values.len() {
+        shell_eprintln!("index {} out of {} values", index, values.len());
+    }
+    values[index]
+}
+
+fn run_exit(code: i32) {
+    std::process::exit(code);
+}
+
+/// 应用状态，由 main 持有，通过闭包暴露给 shell
+#[derive(Default)]
+struct AppState {
+    config: Mutex<HashMap<String, String>>,
+}
+
 fn get_self_pid() -> u64 {
     std::process::id() as u64
 }
 
 fn main() {
-    let mut shell = Shell::new();
+    let mut shell = Shell::with_registered_commands();
 
     reg_shell_cmd!(shell,
         {"hello", print_hello},
         {"add_two", add_two},
         {"print_str", print_str},
         {"add_seven", add_seven},
-        {"run_exit", exit}
+        {"run_exit", run_exit}
     );
 
+    let state = Arc::new(AppState::default());
+
+    // 配置相关的命令挂载在 config 命名空间下，可以用 config.set 或 config set 调用
+    let mut config = Shell::new();
+
+    config
+        .reg_func("set".to_string(), {
+            let state = state.clone();
+            move |key: String, value: String| {
+                state.config.lock().unwrap().insert(key, value);
+            }
+        })
+        .help("设置配置项")
+        .param_names(&["key", "value"]);
+
+    config
+        .reg_func("get".to_string(), {
+            let state = state.clone();
+            move |key: String| state.config.lock().unwrap().get(&key).cloned()
+        })
+        .help("获取配置项")
+        .param_names(&["key"]);
+
+    shell.mount("config", config);
+
+    shell
+        .reg_handler("count_args".to_string(), |args| {
+            Ok(Reply::text(args.len().to_string()))
+        })
+        .help("统计参数个数");
+
     let pid = get_self_pid();
 
     println!("pid: {}", pid);
 
-    match Server::new(
-        shell,
-        format!("/tmp/rust_shell_cmd_{}", pid),
-        format!("/tmp/rust_shell_output_{}", pid),
-    )
-    .run()
-    {
+    match Server::with_config(shell, ServerConfig::new()).run() {
         Ok(_) => (),
         Err(err) => println!("run err: {}", err),
     }
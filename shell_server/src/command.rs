@@ -0,0 +1,553 @@
+//! 类型安全的命令注册
+//!
+//! 任何参数都实现了 [`FromArgument`]、返回值实现了 [`ToReply`] 的 `Fn` 都可以注册为命令，
+//! 参数的转换在调用时完成并做类型检查，不需要任何 `unsafe`。
+
+use shell_core::{Argument, CommandMeta, ParamMeta};
+use std::{fmt::Display, marker::PhantomData, sync::Arc, vec::IntoIter};
+
+use crate::reply::{Reply, ToReply};
+
+/// 从命令行参数转换为函数参数的类型。
+pub trait FromArgument: Sized {
+    /// 参数是否可以省略，`Option<T>` 省略时为 `None`，`Vec<T>` 省略时为空。
+    const OPTIONAL: bool = false;
+
+    /// 将一个参数转换为目标类型，类型不匹配时返回错误信息。
+    fn from_argument(arg: Argument) -> Result<Self, String>;
+
+    /// 从剩余的参数中取出当前参数需要的部分，默认取一个。
+    ///
+    /// `Option<T>` 在参数不足时为 `None`，`Vec<T>` 会取走剩余的全部参数，因此只能作为最后一个参数。
+    fn from_arguments(args: &mut IntoIter<Argument>) -> Result<Self, String> {
+        match args.next() {
+            Some(arg) => Self::from_argument(arg),
+            None => Err(format!("missing argument of {}", short_type_name::<Self>())),
+        }
+    }
+
+    /// 将字节串整体转换为列表，使 `Vec<u8>` 可以直接接收 `b"..."` 和字符串参数，其他类型返回 `None`。
+    fn from_bytes(_bytes: &[u8]) -> Option<Vec<Self>> {
+        None
+    }
+}
+
+/// 绑定到一个参数上的值，`None` 表示参数被省略。
+pub type BoundArgument = Option<Vec<Argument>>;
+
+/// 调用命令失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    /// 参数的个数或类型与命令不符，命令没有执行
+    InvalidArguments(String),
+
+    /// 命令执行失败，包括命令返回的错误和 panic
+    Failed(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CommandError::InvalidArguments(message) | CommandError::Failed(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Failed(message)
+    }
+}
+
+impl From<CommandError> for String {
+    fn from(err: CommandError) -> Self {
+        err.to_string()
+    }
+}
+
+/// 一个可以被 shell 调用的命令。
+pub trait ShellCommand: Send + Sync {
+    /// 使用解析后的参数调用命令。
+    fn call(&self, args: Vec<Argument>) -> Result<Reply, CommandError>;
+
+    /// 使用按参数绑定的值调用命令，第 `i` 项为第 `i` 个参数的值，省略的参数为 `None`。
+    ///
+    /// 默认将各参数的值依次展开后调用 [`ShellCommand::call`]，此时只有末尾的参数可以省略。
+    fn call_bound(&self, args: Vec<BoundArgument>) -> Result<Reply, CommandError> {
+        if let Some(index) = args.iter().position(Option::is_none) {
+            if args[index..].iter().any(Option::is_some) {
+                return Err(CommandError::InvalidArguments(format!(
+                    "argument {} can not be omitted",
+                    index + 1
+                )));
+            }
+        }
+        self.call(args.into_iter().flatten().flatten().collect())
+    }
+
+    /// 参数类型名称列表，用于帮助信息和自动完成。
+    fn param_types(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// 各参数是否可以省略，见 [`FromArgument::OPTIONAL`]。
+    fn param_optional(&self) -> Vec<bool> {
+        vec![]
+    }
+
+    /// 返回值类型名称。
+    fn ret_type(&self) -> String {
+        String::new()
+    }
+}
+
+/// 根据命令的参数类型生成默认的元信息，参数名称为 `arg1`、`arg2`……
+pub(crate) fn meta_of(name: &str, command: &dyn ShellCommand) -> CommandMeta {
+    let optional = command.param_optional();
+    CommandMeta {
+        name: name.to_owned(),
+        params: command
+            .param_types()
+            .into_iter()
+            .enumerate()
+            .map(|(index, ty)| ParamMeta {
+                name: format!("arg{}", index + 1),
+                ty,
+                default: None,
+                optional: optional.get(index).copied().unwrap_or_default(),
+            })
+            .collect(),
+        ret: command.ret_type(),
+        ..Default::default()
+    }
+}
+
+/// 去掉类型名称中的模块路径，如 `alloc::vec::Vec<alloc::string::String>` 转换为 `Vec<String>`。
+fn short_type_name<T: ?Sized>() -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+    for c in std::any::type_name::<T>().chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            word.push(c);
+        } else {
+            result.push_str(word.rsplit("::").next().unwrap_or_default());
+            word.clear();
+            result.push(c);
+        }
+    }
+    result.push_str(word.rsplit("::").next().unwrap_or_default());
+    result
+}
+
+/// 可以转换为 [`ShellCommand`] 的类型。
+///
+/// `Marker` 仅用于区分不同参数个数的实现，调用方不需要关心。
+pub trait IntoCommand<Marker> {
+    /// 转换为可以保存在 shell 中的命令。
+    fn into_command(self) -> Arc<dyn ShellCommand>;
+}
+
+/// 直接处理原始参数的命令，见 [`Shell::reg_handler`](crate::Shell::reg_handler)。
+pub(crate) struct HandlerCommand<F> {
+    pub(crate) func: F,
+}
+
+impl<F> ShellCommand for HandlerCommand<F>
+where
+    F: Fn(Vec<Argument>) -> Result<Reply, String> + Send + Sync,
+{
+    fn call(&self, args: Vec<Argument>) -> Result<Reply, CommandError> {
+        Ok((self.func)(args)?)
+    }
+}
+
+/// 对普通函数或闭包的包装。
+struct FnCommand<F, Marker> {
+    func: F,
+    marker: PhantomData<fn() -> Marker>,
+}
+
+/// 取出第 `index` 个参数，错误信息中带上参数的位置。
+fn next_param<T: FromArgument>(
+    args: &mut IntoIter<Argument>,
+    index: usize,
+) -> Result<T, CommandError> {
+    T::from_arguments(args)
+        .map_err(|err| CommandError::InvalidArguments(format!("argument {}: {}", index, err)))
+}
+
+/// 参数过多的错误
+fn too_many_arguments(expected: usize, count: usize) -> CommandError {
+    CommandError::InvalidArguments(format!(
+        "too many arguments: expect at most {}, got {}",
+        expected, count
+    ))
+}
+
+macro_rules! count_one {
+    ($t:tt) => {
+        1
+    };
+}
+
+macro_rules! impl_into_command {
+    ($(($ty:ident, $var:ident)),*) => {
+        impl<F, R, $($ty,)*> ShellCommand for FnCommand<F, (R, $($ty,)*)>
+        where
+            F: Fn($($ty),*) -> R + Send + Sync,
+            R: ToReply,
+            $($ty: FromArgument,)*
+        {
+            fn call(&self, args: Vec<Argument>) -> Result<Reply, CommandError> {
+                let expected: usize = 0 $(+ count_one!($ty))*;
+                let count = args.len();
+                #[allow(unused_mut)]
+                let mut args = args.into_iter();
+                #[allow(unused_mut, unused_variables)]
+                let mut index = 0;
+                $(
+                    index += 1;
+                    let $var = next_param::<$ty>(&mut args, index)?;
+                )*
+                if args.len() != 0 {
+                    return Err(too_many_arguments(expected, count));
+                }
+                Ok((self.func)($($var),*).to_reply()?)
+            }
+
+            fn call_bound(&self, args: Vec<BoundArgument>) -> Result<Reply, CommandError> {
+                let expected: usize = 0 $(+ count_one!($ty))*;
+                let count: usize = args.iter().flatten().map(Vec::len).sum();
+                let too_many = || too_many_arguments(expected, count);
+                #[allow(unused_mut)]
+                let mut args = args.into_iter();
+                #[allow(unused_mut, unused_variables)]
+                let mut index = 0;
+                $(
+                    index += 1;
+                    // 省略的参数按没有剩余参数处理，`Option<T>` 为 `None`，`Vec<T>` 为空
+                    let mut values = args.next().flatten().unwrap_or_default().into_iter();
+                    let $var = next_param::<$ty>(&mut values, index)?;
+                    if values.len() != 0 {
+                        return Err(too_many());
+                    }
+                )*
+                if args.flatten().any(|values| !values.is_empty()) {
+                    return Err(too_many());
+                }
+                Ok((self.func)($($var),*).to_reply()?)
+            }
+
+            fn param_types(&self) -> Vec<String> {
+                vec![$(short_type_name::<$ty>()),*]
+            }
+
+            fn param_optional(&self) -> Vec<bool> {
+                vec![$($ty::OPTIONAL),*]
+            }
+
+            fn ret_type(&self) -> String {
+                short_type_name::<R>()
+            }
+        }
+
+        impl<F, R, $($ty,)*> IntoCommand<(R, $($ty,)*)> for F
+        where
+            F: Fn($($ty),*) -> R + Send + Sync + 'static,
+            R: ToReply + 'static,
+            $($ty: FromArgument + 'static,)*
+        {
+            fn into_command(self) -> Arc<dyn ShellCommand> {
+                Arc::new(FnCommand {
+                    func: self,
+                    marker: PhantomData,
+                })
+            }
+        }
+    };
+}
+
+impl_into_command!();
+impl_into_command!((A1, a1));
+impl_into_command!((A1, a1), (A2, a2));
+impl_into_command!((A1, a1), (A2, a2), (A3, a3));
+impl_into_command!((A1, a1), (A2, a2), (A3, a3), (A4, a4));
+impl_into_command!((A1, a1), (A2, a2), (A3, a3), (A4, a4), (A5, a5));
+impl_into_command!((A1, a1), (A2, a2), (A3, a3), (A4, a4), (A5, a5), (A6, a6));
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11),
+    (A12, a12)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11),
+    (A12, a12),
+    (A13, a13)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11),
+    (A12, a12),
+    (A13, a13),
+    (A14, a14)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11),
+    (A12, a12),
+    (A13, a13),
+    (A14, a14),
+    (A15, a15)
+);
+impl_into_command!(
+    (A1, a1),
+    (A2, a2),
+    (A3, a3),
+    (A4, a4),
+    (A5, a5),
+    (A6, a6),
+    (A7, a7),
+    (A8, a8),
+    (A9, a9),
+    (A10, a10),
+    (A11, a11),
+    (A12, a12),
+    (A13, a13),
+    (A14, a14),
+    (A15, a15),
+    (A16, a16)
+);
+
+macro_rules! impl_from_argument_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromArgument for $t {
+                fn from_argument(arg: Argument) -> Result<Self, String> {
+                    match arg {
+                        Argument::Int(i, _) => <$t>::try_from(i)
+                            .map_err(|_| format!("{} out of range of {}", i, stringify!($t))),
+                        Argument::UInt(u, _) => <$t>::try_from(u)
+                            .map_err(|_| format!("{} out of range of {}", u, stringify!($t))),
+                        other => Err(format!("expect {}, got {} `{}`", stringify!($t), other.kind(), other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_argument_int!(i8, i16, i32, i64, isize, u16, u32, u64, usize);
+
+impl FromArgument for u8 {
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        match arg {
+            Argument::Int(i, _) => u8::try_from(i).map_err(|_| format!("{} out of range of u8", i)),
+            Argument::UInt(u, _) => {
+                u8::try_from(u).map_err(|_| format!("{} out of range of u8", u))
+            }
+            other => Err(format!("expect u8, got {} `{}`", other.kind(), other)),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Vec<Self>> {
+        Some(bytes.to_vec())
+    }
+}
+
+macro_rules! impl_from_argument_float {
+    ($($t:ty),*) => {
+        $(
+            impl FromArgument for $t {
+                fn from_argument(arg: Argument) -> Result<Self, String> {
+                    match arg {
+                        Argument::Float(f, _) => Ok(f as $t),
+                        Argument::Int(i, _) => Ok(i as $t),
+                        Argument::UInt(u, _) => Ok(u as $t),
+                        other => Err(format!("expect {}, got {} `{}`", stringify!($t), other.kind(), other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_argument_float!(f32, f64);
+
+impl FromArgument for bool {
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        match arg {
+            Argument::Bool(b) => Ok(b),
+            Argument::Int(0, _) => Ok(false),
+            Argument::Int(1, _) => Ok(true),
+            other => Err(format!("expect bool, got {} `{}`", other.kind(), other)),
+        }
+    }
+}
+
+impl FromArgument for char {
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        let s = arg.to_string();
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("expect char, got \"{}\"", s)),
+        }
+    }
+}
+
+/// 数字按原始文本转换，如 `007` 转换为 `"007"`。
+impl FromArgument for String {
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        match arg {
+            Argument::Str(s) => Ok(s),
+            Argument::Bytes(b) => {
+                String::from_utf8(b).map_err(|err| format!("invalid utf-8 string: {}", err))
+            }
+            Argument::Int(_, raw) | Argument::UInt(_, raw) | Argument::Float(_, raw) => Ok(raw),
+            Argument::Bool(b) => Ok(b.to_string()),
+            other => Err(format!("expect String, got {} `{}`", other.kind(), other)),
+        }
+    }
+}
+
+impl<T: FromArgument> FromArgument for Option<T> {
+    const OPTIONAL: bool = true;
+
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        T::from_argument(arg).map(Some)
+    }
+
+    fn from_arguments(args: &mut IntoIter<Argument>) -> Result<Self, String> {
+        if args.len() == 0 {
+            return Ok(None);
+        }
+        T::from_arguments(args).map(Some)
+    }
+}
+
+/// 字节串或字符串参数整体作为列表，如 `Vec<u8>` 接收 `b"..."`。
+fn bytes_of<T: FromArgument>(arg: &Argument) -> Option<Vec<T>> {
+    match arg {
+        Argument::Bytes(b) => T::from_bytes(b),
+        Argument::Str(s) => T::from_bytes(s.as_bytes()),
+        _ => None,
+    }
+}
+
+impl<T: FromArgument> FromArgument for Vec<T> {
+    const OPTIONAL: bool = true;
+
+    fn from_argument(arg: Argument) -> Result<Self, String> {
+        if let Argument::List(items) = arg {
+            return items.into_iter().map(T::from_argument).collect();
+        }
+        match bytes_of(&arg) {
+            Some(v) => Ok(v),
+            None => Ok(vec![T::from_argument(arg)?]),
+        }
+    }
+
+    fn from_arguments(args: &mut IntoIter<Argument>) -> Result<Self, String> {
+        if let [arg] = args.as_slice() {
+            if let Some(v) = bytes_of(arg) {
+                args.next();
+                return Ok(v);
+            }
+        }
+        args.map(T::from_argument).collect()
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use shell_core::{default_dir, DiscoveryRecord, DEFAULT_INSTANCE};
+
+/// 服务端的配置
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_server::ServerConfig;
+///
+/// let mut config = ServerConfig::new();
+/// config.dir("/run/my_app").name("db");
+///
+/// assert_eq!(
+///     config.socket_path(),
+///     std::path::PathBuf::from(format!("/run/my_app/{}.db.sock", std::process::id()))
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 套接字和发现记录所在的目录
+    dir: PathBuf,
+
+    /// 实例名称
+    name: String,
+
+    /// 指定的套接字路径，不指定时在 `dir` 中按进程 id 和实例名称生成
+    socket: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerConfig {
+    /// 创建默认配置，目录见 [`default_dir`]，实例名称为 `default`。
+    pub fn new() -> ServerConfig {
+        ServerConfig {
+            dir: default_dir(),
+            name: DEFAULT_INSTANCE.to_owned(),
+            socket: None,
+        }
+    }
+
+    /// 设置套接字和发现记录所在的目录。
+    pub fn dir(&mut self, dir: impl Into<PathBuf>) -> &mut ServerConfig {
+        self.dir = dir.into();
+        self
+    }
+
+    /// 设置实例名称，同一个进程中运行多个服务端时需要使用不同的名称。
+    ///
+    /// 名称用于文件名，只能包含字母、数字、`-`、`_` 和 `.`，且不能以 `.` 开头，
+    /// 否则 [`Server::run`](crate::Server::run) 返回错误。
+    pub fn name(&mut self, name: &str) -> &mut ServerConfig {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// 指定套接字路径，发现记录仍然写入 [`ServerConfig::dir`] 设置的目录。
+    pub fn socket(&mut self, path: impl Into<PathBuf>) -> &mut ServerConfig {
+        self.socket = Some(path.into());
+        self
+    }
+
+    /// 套接字和发现记录所在的目录
+    pub fn get_dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// 实例名称
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// 指定的套接字路径
+    pub fn get_socket(&self) -> Option<&PathBuf> {
+        self.socket.as_ref()
+    }
+
+    /// 套接字路径
+    pub fn socket_path(&self) -> PathBuf {
+        match &self.socket {
+            Some(path) => path.clone(),
+            None => DiscoveryRecord::socket_in(&self.dir, std::process::id(), &self.name),
+        }
+    }
+}
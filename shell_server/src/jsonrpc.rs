@@ -0,0 +1,283 @@
+//! JSON-RPC 2.0 模式
+//!
+//! 连接建立后客户端发送的第一个字节为 `{` 或 `[` 时，该连接使用 JSON-RPC 2.0 通信，
+//! 也可以在握手时声明 [`Capability::JsonRpc`](shell_core::Capability::JsonRpc)，握手后切换为 JSON-RPC 2.0。
+//! 每行一个请求（或批量请求），服务端每行回复一个响应。
+//!
+//! 已注册的命令即为方法，参数以数组按位置传递或以对象按名称传递，返回值以 JSON 返回。
+//! `Vec<T>` 类型的参数传递数组，按名称传递时值为 `null` 的参数视为省略。
+//! 命令执行过程中的输出以 `output` 通知发送，`id` 为产生输出的请求的 id：
+//!
+//! ```text
+//! --> {"jsonrpc":"2.0","id":1,"method":"add_two","params":[1,2]}
+//! <-- {"jsonrpc":"2.0","method":"output","params":{"id":1,"stream":"stdout","text":"1 + 2 = 3\n"}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":3}
+//! ```
+//!
+//! 内置的 `help` 方法返回所有方法的元信息（名称、参数、返回值类型和帮助信息），
+//! 参数 `command` 为命令名称或命名空间时只返回该命令或命名空间中的命令：
+//!
+//! ```text
+//! --> {"jsonrpc":"2.0","id":2,"method":"help","params":{"command":"add_two"}}
+//! <-- {"jsonrpc":"2.0","id":2,"result":[{"name":"add_two","params":[...],"ret":"i64",...}]}
+//! ```
+
+use std::{
+    io::{BufRead, Write},
+    os::unix::net::UnixStream,
+};
+
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use shell_core::{Argument, CommandMeta};
+
+use crate::{
+    command::CommandError,
+    output::redirect_output,
+    shell::{bind_arguments, NamedValues, Shell, HELP_COMMAND},
+};
+
+/// 无法解析的 JSON
+const PARSE_ERROR: i64 = -32700;
+
+/// 不是有效的请求
+const INVALID_REQUEST: i64 = -32600;
+
+/// 命令不存在
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// 参数无法绑定到命令的参数列表
+const INVALID_PARAMS: i64 = -32602;
+
+/// 命令执行失败
+const COMMAND_FAILED: i64 = -32000;
+
+/// 请求，没有 `id` 的请求为通知，不回复响应，`id` 为 `null` 的请求仍然需要回复
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+}
+
+/// 字段存在时为 `Some`，包括值为 `null` 的情况
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// 将写入的数据作为 `output` 通知发送给客户端
+struct NotificationWriter {
+    conn: UnixStream,
+    /// 产生输出的请求 id
+    id: Value,
+    stream: &'static str,
+}
+
+impl Write for NotificationWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "output",
+            "params": {
+                "id": self.id,
+                "stream": self.stream,
+                "text": String::from_utf8_lossy(buf),
+            },
+        });
+        self.conn
+            .write_all(format!("{}\n", notification).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+fn response(id: Value, ret: Result<Value, (i64, String)>) -> Value {
+    match ret {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": code, "message": message},
+        }),
+    }
+}
+
+/// 将一个 JSON 值转换为一个参数，数组、对象和 `null` 以 JSON 文本作为字符串传递。
+fn argument_of(value: Value) -> Argument {
+    match value {
+        Value::Bool(b) => Argument::Bool(b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Argument::Int(i, n.to_string()),
+            (None, Some(u)) => Argument::UInt(u, n.to_string()),
+            _ => Argument::Float(n.as_f64().unwrap_or_default(), n.to_string()),
+        },
+        Value::String(s) => Argument::Str(s),
+        other => Argument::Str(other.to_string()),
+    }
+}
+
+/// 将一个参数的值转换为参数，数组的每个元素为一个参数。
+fn values_of(value: Value) -> Vec<Argument> {
+    match value {
+        Value::Array(items) => items.into_iter().map(argument_of).collect(),
+        other => vec![argument_of(other)],
+    }
+}
+
+/// 将请求的参数转换为按位置或按名称传递的参数。
+fn arguments_of(params: Option<Value>) -> Result<Vec<NamedValues>, String> {
+    match params {
+        None => Ok(vec![]),
+        Some(Value::Array(mut items)) => {
+            // 末尾的 null 视为省略，中间的 null 无法按位置传递
+            while items.last() == Some(&Value::Null) {
+                items.pop();
+            }
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| match item {
+                    Value::Null => Err(format!(
+                        "argument {}: null can only be used for trailing or named parameters",
+                        index + 1
+                    )),
+                    item => Ok((None, values_of(item))),
+                })
+                .collect()
+        }
+        Some(Value::Object(map)) => Ok(map
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(name, value)| (Some(name), values_of(value)))
+            .collect()),
+        Some(_) => Err("params must be an array or an object".to_owned()),
+    }
+}
+
+/// 内置的 `help` 方法，返回所有命令或指定的命令和命名空间中的命令的元信息。
+fn help(shell: &Shell, params: Option<Value>) -> Result<Value, (i64, String)> {
+    let topic = match params {
+        None => None,
+        Some(Value::Array(items)) => items.into_iter().next(),
+        Some(Value::Object(mut map)) => map.remove("command"),
+        Some(_) => {
+            return Err((
+                INVALID_PARAMS,
+                "params must be an array or an object".to_owned(),
+            ))
+        }
+    };
+    let metas = shell.get_command_metas();
+    let metas: Vec<CommandMeta> = match topic {
+        None | Some(Value::Null) => metas,
+        Some(Value::String(topic)) => {
+            let prefix = format!("{}.", topic);
+            let metas: Vec<CommandMeta> = metas
+                .into_iter()
+                .filter(|meta| meta.name == topic || meta.name.starts_with(&prefix))
+                .collect();
+            if metas.is_empty() {
+                return Err((INVALID_PARAMS, format!("{} not found", topic)));
+            }
+            metas
+        }
+        Some(_) => return Err((INVALID_PARAMS, "command must be a string".to_owned())),
+    };
+    serde_json::to_value(metas).map_err(|err| (COMMAND_FAILED, err.to_string()))
+}
+
+/// 执行一个请求的命令，返回命令的返回值。
+fn call(shell: &Shell, conn: &UnixStream, request: Request) -> Result<Value, (i64, String)> {
+    if request.method == HELP_COMMAND && shell.get_command_meta(HELP_COMMAND).is_none() {
+        return help(shell, request.params);
+    }
+    let meta = shell
+        .get_command_meta(&request.method)
+        .ok_or((METHOD_NOT_FOUND, format!("{} not found", request.method)))?;
+    let args = arguments_of(request.params)
+        .and_then(|args| bind_arguments(meta, args))
+        .map_err(|err| (INVALID_PARAMS, err))?;
+
+    let id = request.id.unwrap_or(Value::Null);
+    let writer = |stream| -> Result<Box<dyn Write>, (i64, String)> {
+        Ok(Box::new(NotificationWriter {
+            conn: conn
+                .try_clone()
+                .map_err(|err| (COMMAND_FAILED, err.to_string()))?,
+            id: id.clone(),
+            stream,
+        }))
+    };
+    let _guard = redirect_output(writer("stdout")?, writer("stderr")?);
+    let reply = shell
+        .invoke(&request.method, args)
+        .map_err(|err| match err {
+            CommandError::InvalidArguments(message) => (INVALID_PARAMS, message),
+            CommandError::Failed(message) => (COMMAND_FAILED, message),
+        })?;
+    Ok(match (reply.json, reply.text) {
+        (Some(json), _) => json,
+        (None, Some(text)) => Value::String(text),
+        (None, None) => Value::Null,
+    })
+}
+
+/// 处理一个请求，通知没有响应。
+fn handle_request(shell: &Shell, conn: &UnixStream, request: Value) -> Option<Value> {
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        _ => {
+            return Some(response(
+                Value::Null,
+                Err((INVALID_REQUEST, "invalid request".to_owned())),
+            ))
+        }
+    };
+    let id = request.id.clone();
+    let ret = call(shell, conn, request);
+    id.map(|id| response(id, ret))
+}
+
+/// 处理一行请求，可以是单个请求或批量请求，没有需要回复的响应时返回 `None`。
+fn handle_line(shell: &Shell, conn: &UnixStream, line: &str) -> Option<Value> {
+    match serde_json::from_str::<Value>(line) {
+        Err(err) => Some(response(Value::Null, Err((PARSE_ERROR, err.to_string())))),
+        Ok(Value::Array(requests)) if requests.is_empty() => Some(response(
+            Value::Null,
+            Err((INVALID_REQUEST, "empty batch".to_owned())),
+        )),
+        Ok(Value::Array(requests)) => {
+            let responses: Vec<Value> = requests
+                .into_iter()
+                .filter_map(|request| handle_request(shell, conn, request))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(request) => handle_request(shell, conn, request),
+    }
+}
+
+/// 在连接上以 JSON-RPC 2.0 处理请求，直到连接关闭。
+pub(crate) fn serve(
+    mut conn: UnixStream,
+    reader: impl BufRead,
+    shell: &Shell,
+) -> Result<(), String> {
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(shell, &conn, &line) {
+            conn.write_all(format!("{}\n", response).as_bytes())
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    Ok(())
+}
@@ -3,20 +3,19 @@
 //! # 示例
 //!  
 //! ```no_run
-//! use libc::exit;
-//! use shell_server::{reg_shell_cmd, Server, Shell};
+//! use shell_server::{reg_shell_cmd, shell_println, Server, ServerConfig, Shell};
 //! fn print_hello() {
-//!     println!("Hello, world!");
+//!     shell_println!("Hello, world!");
 //! }
 //! fn add_two(a: i64, b: i64) -> i64 {
-//!     println!("{} + {} = {}", a, b, a + b);
+//!     shell_println!("{} + {} = {}", a, b, a + b);
 //!     return a + b;
 //! }
-//! fn print_str(s: &String) {
-//!     println!("{}", s);
+//! fn print_str(s: String) {
+//!     shell_println!("{}", s);
 //! }
 //! fn add_seven(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> i64 {
-//!     println!(
+//!     shell_println!(
 //!         "{} + {} + {} + {} + {} + {} + {} = {}",
 //!         a,
 //!         b,
@@ -29,6 +28,9 @@
 //!     );
 //!     return a + b + c + d + e + f + g;
 //! }
+//! fn run_exit(code: i32) {
+//!     std::process::exit(code);
+//! }
 //! fn get_self_pid() -> u64 {
 //!     return std::process::id() as u64;
 //! }
@@ -39,25 +41,34 @@
 //!         {"add_two", add_two},
 //!         {"print_str", print_str},
 //!         {"add_seven", add_seven},
-//!         {"run_exit", exit}
+//!         {"run_exit", run_exit}
 //!     );
 //!     let pid = get_self_pid();
 //!     println!("pid: {}", pid);
-//!     match Server::new(
-//!         shell,
-//!         format!("/tmp/rust_shell_cmd_{}", pid),
-//!         format!("/tmp/rust_shell_output_{}", pid),
-//!     )
-//!     .run()
-//!     {
+//!     match Server::with_config(shell, ServerConfig::new()).run() {
 //!         Ok(_) => (),
 //!         Err(err) => println!("run err: {}", err),
 //!     }
 //! }
 //!```
 
+#![allow(clippy::needless_doctest_main)]
+
+mod command;
+mod config;
+mod jsonrpc;
+mod output;
+mod registry;
+mod reply;
 mod server;
 mod shell;
 
+pub use command::*;
+pub use config::*;
+pub use output::*;
+pub use registry::*;
+pub use reply::*;
 pub use server::*;
 pub use shell::*;
+pub use shell_core::{CommandMeta, ParamMeta};
+pub use shell_macros::shell_command;
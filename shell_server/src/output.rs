@@ -0,0 +1,268 @@
+//! 命令输出
+//!
+//! 命令通过 [`shell_println!`](crate::shell_println) 和 [`shell_print!`](crate::shell_print)
+//! 输出的内容只会发送给发起命令的客户端，进程自身的标准输出不受影响。
+//! 没有客户端时（如直接调用 [`Shell::run_command`](crate::Shell::run_command)）输出到标准输出。
+//!
+//! [`shell_eprintln!`](crate::shell_eprintln) 和 [`shell_eprint!`](crate::shell_eprint)
+//! 的输出通过错误输出流发送给客户端，由客户端以红色显示，没有客户端时输出到标准错误。
+//! 命令执行过程中发生的 panic 信息和调用栈也会以同样的方式发送给客户端。
+//!
+//! 通过 [`SessionLogger`] 安装日志器后，命令执行过程中 `log` 的日志记录同样作为错误信息发送给客户端。
+//!
+//! 输出绑定在执行命令的线程上，命令中新建的线程的输出不会发送给客户端。
+//! 直接使用 `print!`、`eprintln!` 等宏或写入标准输出、标准错误的内容不会被捕获，仍然输出到进程自身。
+
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    fmt::Arguments,
+    io::{stderr, stdout, Write},
+    panic,
+    sync::Once,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+thread_local! {
+    /// 当前线程上正在执行的命令的输出和错误输出
+    static SESSION_OUTPUT: RefCell<Option<SessionOutput>> = const { RefCell::new(None) };
+}
+
+/// 一个命令的输出和错误输出
+struct SessionOutput {
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+}
+
+/// 恢复之前的输出，由 [`redirect_output`] 返回。
+pub struct OutputGuard {
+    previous: Option<SessionOutput>,
+}
+
+impl Drop for OutputGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SESSION_OUTPUT.with(|output| *output.borrow_mut() = previous);
+    }
+}
+
+/// 将当前线程上的命令输出重定向到 `stdout`，错误输出重定向到 `stderr`，
+/// 返回的守卫被丢弃时恢复之前的输出。
+///
+/// # 示例
+///
+/// ```rust
+/// use shell_server::{redirect_output, shell_eprintln, shell_println};
+///
+/// let out = std::env::temp_dir().join("shell_output_example.out");
+/// let err = std::env::temp_dir().join("shell_output_example.err");
+/// {
+///     let _guard = redirect_output(
+///         Box::new(std::fs::File::create(&out).unwrap()),
+///         Box::new(std::fs::File::create(&err).unwrap()),
+///     );
+///     shell_println!("hello {}", 42);
+///     shell_eprintln!("oops");
+/// }
+/// assert_eq!(std::fs::read_to_string(&out).unwrap(), "hello 42\n");
+/// assert_eq!(std::fs::read_to_string(&err).unwrap(), "oops\n");
+/// ```
+pub fn redirect_output(stdout: Box<dyn Write>, stderr: Box<dyn Write>) -> OutputGuard {
+    let previous = SESSION_OUTPUT.with(|output| {
+        output
+            .borrow_mut()
+            .replace(SessionOutput { stdout, stderr })
+    });
+    OutputGuard { previous }
+}
+
+/// 输出到当前命令的客户端，没有客户端时输出到标准输出。
+#[doc(hidden)]
+pub fn __print(args: Arguments) {
+    SESSION_OUTPUT.with(|output| match output.borrow_mut().as_mut() {
+        // 客户端断开时忽略错误，命令继续执行
+        Some(output) => {
+            // 一次写入完整的内容，避免输出被拆分为多条消息
+            let _ = output
+                .stdout
+                .write_all(args.to_string().as_bytes())
+                .and_then(|_| output.stdout.flush());
+        }
+        None => {
+            let _ = stdout().write_fmt(args);
+        }
+    })
+}
+
+/// 输出错误信息到当前命令的客户端，没有客户端时输出到标准错误。
+#[doc(hidden)]
+pub fn __eprint(args: Arguments) {
+    SESSION_OUTPUT.with(|output| match output.borrow_mut().as_mut() {
+        Some(output) => {
+            let _ = output
+                .stderr
+                .write_all(args.to_string().as_bytes())
+                .and_then(|_| output.stderr.flush());
+        }
+        None => {
+            let _ = stderr().write_fmt(args);
+        }
+    })
+}
+
+/// 将错误信息写入当前命令的错误输出，没有正在执行的命令或正在写入时返回 `false`。
+fn write_session_stderr(message: &str) -> bool {
+    SESSION_OUTPUT.with(|output| match output.try_borrow_mut() {
+        Ok(mut output) => match output.as_mut() {
+            Some(output) => {
+                let _ = output
+                    .stderr
+                    .write_all(message.as_bytes())
+                    .and_then(|_| output.stderr.flush());
+                true
+            }
+            None => false,
+        },
+        Err(_) => false,
+    })
+}
+
+/// 将 `log` 的日志记录发送给发起命令的客户端的日志器
+///
+/// 命令执行过程中的日志记录作为错误信息发送给客户端，
+/// 其他日志记录交给 `fallback` 处理，没有 `fallback` 时输出到标准错误。
+///
+/// # 示例
+///
+/// ```rust
+/// use log::LevelFilter;
+/// use shell_server::{redirect_output, SessionLogger};
+///
+/// SessionLogger::new(None).install(LevelFilter::Info).unwrap();
+///
+/// let err = std::env::temp_dir().join("shell_logger_example.err");
+/// {
+///     let _guard = redirect_output(
+///         Box::new(std::io::sink()),
+///         Box::new(std::fs::File::create(&err).unwrap()),
+///     );
+///     log::info!(target: "app", "loaded {} items", 3);
+///     log::debug!("ignored");
+/// }
+/// assert_eq!(
+///     std::fs::read_to_string(&err).unwrap(),
+///     "[INFO app] loaded 3 items\n"
+/// );
+/// ```
+pub struct SessionLogger {
+    fallback: Option<Box<dyn Log>>,
+}
+
+impl SessionLogger {
+    /// 创建日志器，`fallback` 处理没有在命令中产生的日志记录。
+    pub fn new(fallback: Option<Box<dyn Log>>) -> SessionLogger {
+        SessionLogger { fallback }
+    }
+
+    /// 安装为全局日志器并设置最大日志级别，已经安装了其他日志器时返回错误。
+    pub fn install(self, level: LevelFilter) -> Result<(), String> {
+        log::set_boxed_logger(Box::new(self))
+            .map(|_| log::set_max_level(level))
+            .map_err(|err| format!("install logger failed: {}", err))
+    }
+}
+
+impl Log for SessionLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = format!(
+            "[{} {}] {}\n",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if write_session_stderr(&message) {
+            return;
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.log(record),
+            None => {
+                let _ = stderr().write_all(message.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(fallback) = &self.fallback {
+            fallback.flush();
+        }
+    }
+}
+
+/// 安装 panic hook，命令执行过程中发生的 panic 的信息和调用栈发送给发起命令的客户端，
+/// 其他线程的 panic 仍由之前的 hook 处理。
+pub(crate) fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // 没有正在执行的命令或输出时发生 panic 时无法发送给客户端
+            let in_session = SESSION_OUTPUT
+                .with(|output| output.try_borrow().is_ok_and(|output| output.is_some()));
+            let sent = in_session
+                && write_session_stderr(&format!(
+                    "{}\nstack backtrace:\n{}\n",
+                    info,
+                    Backtrace::force_capture()
+                ));
+            if !sent {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// 与 `print!` 相同，但输出发送给发起命令的客户端。
+#[macro_export]
+macro_rules! shell_print {
+    ($($arg:tt)*) => {
+        $crate::__print(format_args!($($arg)*))
+    };
+}
+
+/// 与 `println!` 相同，但输出发送给发起命令的客户端。
+#[macro_export]
+macro_rules! shell_println {
+    () => {
+        $crate::__print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::__print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// 与 `eprint!` 相同，但输出作为错误信息发送给发起命令的客户端。
+#[macro_export]
+macro_rules! shell_eprint {
+    ($($arg:tt)*) => {
+        $crate::__eprint(format_args!($($arg)*))
+    };
+}
+
+/// 与 `eprintln!` 相同，但输出作为错误信息发送给发起命令的客户端。
+#[macro_export]
+macro_rules! shell_eprintln {
+    () => {
+        $crate::__eprint(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::__eprint(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,39 @@
+//! `#[shell_command]` 自动注册的命令表
+
+use linkme::distributed_slice;
+use shell_core::CommandMeta;
+
+use crate::shell::Shell;
+
+/// 由 `#[shell_command]` 生成的命令条目
+pub struct CommandEntry {
+    /// 命令名称
+    pub name: &'static str,
+
+    /// 帮助信息
+    pub help: &'static str,
+
+    /// 参数名称和类型
+    pub params: &'static [(&'static str, &'static str)],
+
+    /// 参数的默认值
+    pub defaults: &'static [(&'static str, &'static str)],
+
+    /// 返回值类型
+    pub ret: &'static str,
+
+    /// 使用示例
+    pub examples: &'static [&'static str],
+
+    /// 将命令注册到 shell 中
+    pub register: for<'a> fn(&'a mut Shell) -> &'a mut CommandMeta,
+}
+
+/// 所有通过 `#[shell_command]` 标注的命令
+#[distributed_slice]
+pub static SHELL_COMMANDS: [CommandEntry];
+
+#[doc(hidden)]
+pub mod __private {
+    pub use linkme;
+}
@@ -0,0 +1,240 @@
+//! 命令的返回值
+//!
+//! 命令的返回值通过 [`ToReply`] 转换为 [`Reply`]，同时保留文本和 JSON 两种表示，
+//! 客户端可以选择以哪种格式显示。基本类型、`String`、`Option`、`Vec` 和常用的集合类型可以直接返回，
+//! 其他类型可以使用 [`DisplayReply`]、[`DebugReply`] 或 [`JsonReply`] 包装。
+//! 返回 `Result<T, E>` 的命令在出错时，错误信息会作为失败的回复发送给客户端。
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{Debug, Display},
+};
+
+/// 命令执行后的回复
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    /// 返回值的文本表示，没有返回值时为 `None`。
+    pub text: Option<String>,
+
+    /// 返回值的 JSON 表示，返回值不支持序列化时为 `None`。
+    pub json: Option<Value>,
+}
+
+/// 回复的显示格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplyFormat {
+    /// 使用 `Display`/`Debug` 渲染的文本
+    #[default]
+    Text,
+
+    /// JSON
+    Json,
+}
+
+impl ReplyFormat {
+    /// 从名称解析显示格式，支持 `text` 和 `json`。
+    pub fn from_name(name: &str) -> Option<ReplyFormat> {
+        match name {
+            "text" => Some(ReplyFormat::Text),
+            "json" => Some(ReplyFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl Reply {
+    /// 创建一个没有返回值的回复。
+    pub fn none() -> Reply {
+        Reply {
+            text: None,
+            json: None,
+        }
+    }
+
+    /// 创建一个只有文本表示的回复。
+    pub fn text(text: String) -> Reply {
+        Reply {
+            text: Some(text),
+            json: None,
+        }
+    }
+
+    /// 创建一个同时带有文本和 JSON 表示的回复，序列化失败时只保留文本。
+    pub fn with_json<T: Serialize + ?Sized>(text: String, value: &T) -> Reply {
+        Reply {
+            text: Some(text),
+            json: serde_json::to_value(value).ok(),
+        }
+    }
+
+    /// 按指定格式渲染回复，没有返回值时返回 `None`。
+    ///
+    /// JSON 格式下，如果返回值不支持序列化，则将文本表示作为 JSON 字符串输出。
+    pub fn render(&self, format: ReplyFormat) -> Option<String> {
+        match format {
+            ReplyFormat::Text => self.text.clone(),
+            ReplyFormat::Json => match (&self.json, &self.text) {
+                (Some(json), _) => Some(json.to_string()),
+                (None, Some(text)) => Some(Value::String(text.clone()).to_string()),
+                (None, None) => None,
+            },
+        }
+    }
+}
+
+/// 可以作为命令返回值的类型。
+pub trait ToReply {
+    /// 将返回值转换为回复，命令执行失败时返回错误信息。
+    fn to_reply(self) -> Result<Reply, String>;
+}
+
+impl ToReply for () {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::none())
+    }
+}
+
+impl ToReply for Reply {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(self)
+    }
+}
+
+macro_rules! impl_to_reply_display {
+    ($($t:ty),*) => {
+        $(
+            impl ToReply for $t {
+                fn to_reply(self) -> Result<Reply, String> {
+                    Ok(Reply::with_json(self.to_string(), &self))
+                }
+            }
+        )*
+    };
+}
+
+impl_to_reply_display!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, bool, char, String, &str
+);
+
+macro_rules! impl_to_reply_float {
+    ($($t:ty),*) => {
+        $(
+            impl ToReply for $t {
+                // 使用 Debug 格式，保证 2.0 不会显示为 2
+                fn to_reply(self) -> Result<Reply, String> {
+                    Ok(Reply::with_json(format!("{:?}", self), &self))
+                }
+            }
+        )*
+    };
+}
+
+impl_to_reply_float!(f32, f64);
+
+impl<T: ToReply> ToReply for Option<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        match self {
+            Some(value) => value.to_reply(),
+            None => Ok(Reply {
+                text: Some("None".to_owned()),
+                json: Some(Value::Null),
+            }),
+        }
+    }
+}
+
+impl<T: Debug + Serialize> ToReply for Vec<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self))
+    }
+}
+
+impl<T: Debug + Serialize, const N: usize> ToReply for [T; N] {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self[..]))
+    }
+}
+
+impl<K: Debug + Serialize, V: Debug + Serialize, S> ToReply for HashMap<K, V, S> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self))
+    }
+}
+
+impl<K: Debug + Serialize, V: Debug + Serialize> ToReply for BTreeMap<K, V> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self))
+    }
+}
+
+impl<T: Debug + Serialize, S> ToReply for HashSet<T, S> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self))
+    }
+}
+
+impl<T: Debug + Serialize> ToReply for BTreeSet<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::with_json(format!("{:?}", self), &self))
+    }
+}
+
+/// 命令执行失败时，错误信息会通过 `Display` 发送给发起命令的客户端。
+impl<T: ToReply, E: Display> ToReply for Result<T, E> {
+    fn to_reply(self) -> Result<Reply, String> {
+        self.map_err(|err| err.to_string())?.to_reply()
+    }
+}
+
+/// 使用 `Display` 渲染返回值，不提供 JSON 表示。
+pub struct DisplayReply<T>(pub T);
+
+impl<T: Display> ToReply for DisplayReply<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::text(self.0.to_string()))
+    }
+}
+
+/// 使用 `Debug` 渲染返回值，不提供 JSON 表示。
+pub struct DebugReply<T>(pub T);
+
+impl<T: Debug> ToReply for DebugReply<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        Ok(Reply::text(format!("{:?}", self.0)))
+    }
+}
+
+/// 使用 `serde` 序列化返回值，文本表示为格式化后的 JSON。
+///
+/// # 示例
+///
+/// ```rust
+/// use serde::Serialize;
+/// use shell_server::{JsonReply, ReplyFormat, ToReply};
+///
+/// #[derive(Serialize)]
+/// struct Stats {
+///     active: u32,
+///     idle: u32,
+/// }
+///
+/// let reply = JsonReply(Stats { active: 3, idle: 1 }).to_reply().unwrap();
+/// assert_eq!(
+///     reply.render(ReplyFormat::Json).unwrap(),
+///     r#"{"active":3,"idle":1}"#
+/// );
+/// ```
+pub struct JsonReply<T>(pub T);
+
+impl<T: Serialize> ToReply for JsonReply<T> {
+    fn to_reply(self) -> Result<Reply, String> {
+        let json =
+            serde_json::to_value(&self.0).map_err(|err| format!("serialize failed: {}", err))?;
+        Ok(Reply {
+            text: serde_json::to_string_pretty(&json).ok(),
+            json: Some(json),
+        })
+    }
+}
@@ -1,36 +1,102 @@
 use std::{
-    io::Read,
-    os::{
-        fd::AsRawFd,
-        unix::net::{UnixListener, UnixStream},
+    fs::{File, OpenOptions, Permissions, TryLockError},
+    io::{BufRead, BufReader, Cursor, ErrorKind, Read, Write},
+    os::unix::{
+        fs::{FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
     },
-    thread::{spawn, JoinHandle},
+    path::{Path, PathBuf},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
 };
 
-use libc::{c_int, close, dup, dup2, STDOUT_FILENO};
-use shell_core::{read_line, write_line};
+use shell_core::{
+    is_valid_instance_name, lock_path, split_command, write_message, Capability, DiscoveryRecord,
+    Hello, Message, MessageReader, Stream, SHARED_DIR,
+};
+
+use crate::{
+    config::ServerConfig, jsonrpc, output::redirect_output, reply::ReplyFormat, shell::Shell,
+};
+
+/// 锁文件被占用时的重试次数
+const LOCK_RETRIES: u32 = 10;
+
+/// 锁文件被占用时的重试间隔
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 将写入的数据作为 [`Message::Output`] 发送给客户端
+struct OutputWriter {
+    conn: UnixStream,
+    /// 产生输出的请求 id
+    id: u64,
+    stream: Stream,
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let message = Message::Output {
+            id: self.id,
+            stream: self.stream,
+            data: buf.to_vec(),
+        };
+        write_message(&mut self.conn, &message).map_err(std::io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+/// 一个连接的会话状态
+///
+/// 每个连接是一个独立的会话，命令、命令的输出和执行结果都在这个连接上按顺序传递，
+/// 客户端收到执行结果时该命令的输出已经全部收到。
+struct Session {
+    /// 客户端连接
+    conn: UnixStream,
 
-use crate::shell::Shell;
+    /// 返回值的显示格式
+    format: ReplyFormat,
+
+    /// 执行命令时是否输出开始和结束标记
+    banner: bool,
+}
 
 /// 一个服务器，侦听传入的 Unix 域套接字 (UDS) 连接并处理命令。
 pub struct Server {
     /// 要在服务器上执行的 shell 实例。
     shell: Shell,
 
-    /// Unix 域套接字 (UDS) 路径，用于侦听命令。
-    uds_cmd_path: String,
+    /// 服务器的配置
+    config: ServerConfig,
 
-    /// Unix 域套接字 (UDS) 路径，用于侦听输出。
-    uds_output_path: String,
+    /// Unix 域套接字 (UDS) 路径，用于侦听客户端连接。
+    uds_path: PathBuf,
+
+    /// 锁文件，侦听期间保持锁定，防止其他进程使用同一个路径
+    lock: Option<File>,
+
+    /// 发现记录的路径
+    record: Option<PathBuf>,
 }
 
 /// 实现 Drop trait，以便在 Server 实例被丢弃时删除 Unix 域套接字 (UDS) 文件。
 impl Drop for Server {
     /// 当 Server 实例被丢弃时，此函数将被调用。
-    /// 它将删除 `uds_cmd_path` 和 `uds_output_path` 所指向的 Unix 域套接字 (UDS) 文件。
+    /// 如果套接字由当前进程创建，它将删除 `uds_path` 所指向的 Unix 域套接字 (UDS) 文件和发现记录。
+    ///
+    /// 套接字在释放锁之前删除，避免删除其他进程随后创建的同名套接字。锁文件保留，
+    /// 删除被锁定的锁文件会使两个进程分别锁定新旧两个文件，都认为自己独占该路径。
     fn drop(&mut self) {
-        let _ = std::fs::remove_file(&self.uds_cmd_path);
-        let _ = std::fs::remove_file(&self.uds_output_path);
+        if let Some(record) = self.record.take() {
+            let _ = std::fs::remove_file(record);
+        }
+        if let Some(lock) = self.lock.take() {
+            let _ = std::fs::remove_file(&self.uds_path);
+            drop(lock);
+        }
     }
 }
 
@@ -40,95 +106,299 @@ impl Server {
     /// # Arguments
     ///
     /// * `shell_` - 要在服务器上执行的 shell 实例。
-    /// * `uds_cmd_path_` - Unix 域套接字 (UDS) 路径，用于侦听命令。
-    /// * `uds_output_path_` - Unix 域套接字 (UDS) 路径，用于侦听输出。
+    /// * `uds_path_` - Unix 域套接字 (UDS) 路径，用于侦听客户端连接。
     ///
     /// # Returns
     ///
     /// 一个新的 Server 实例。
-    pub fn new(shell_: Shell, uds_cmd_path_: String, uds_output_path_: String) -> Server {
+    pub fn new(shell_: Shell, uds_path_: String) -> Server {
+        let mut config = ServerConfig::new();
+        config.socket(uds_path_);
+        Server::with_config(shell_, config)
+    }
+
+    /// 使用指定的配置创建一个 Server 实例。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,no_run
+    /// use shell_server::{Server, ServerConfig, Shell};
+    ///
+    /// let mut config = ServerConfig::new();
+    /// config.name("worker");
+    /// Server::with_config(Shell::with_registered_commands(), config)
+    ///     .run()
+    ///     .unwrap();
+    /// ```
+    pub fn with_config(shell: Shell, config: ServerConfig) -> Server {
         Server {
-            shell: shell_,
-            uds_cmd_path: uds_cmd_path_,
-            uds_output_path: uds_output_path_,
+            shell,
+            uds_path: config.socket_path(),
+            config,
+            lock: None,
+            record: None,
         }
     }
 
-    fn handle_cmd_connect(mut conn: UnixStream, shell: Shell) -> Result<(), String> {
-        write_line(&mut conn, &shell.get_reg_commands().join(" "))?;
-        loop {
-            let s = read_line(&mut conn)?;
-            if let Err(err) = shell.run_command(&s) {
-                println!("Error: {}", err);
+    /// 创建套接字目录，共享目录允许所有用户写入。
+    ///
+    /// 共享目录可能由其他用户预先创建，只使用当前用户所有的目录，或 root 所有、权限为 1777
+    /// 的目录（设置了粘滞位，其他用户不能删除或替换当前用户的文件）。
+    fn create_dir(dir: &Path) -> Result<(), String> {
+        let shared = dir == Path::new(SHARED_DIR);
+        if !dir.exists() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format!("create {} failed: {}", dir.display(), err))?;
+            if shared {
+                let _ = std::fs::set_permissions(dir, Permissions::from_mode(0o1777));
             }
         }
+        if !shared {
+            return Ok(());
+        }
+        let meta = std::fs::symlink_metadata(dir)
+            .map_err(|err| format!("stat {} failed: {}", dir.display(), err))?;
+        let owner = meta.uid();
+        let trusted = meta.is_dir()
+            && (owner == unsafe { libc::geteuid() }
+                || (owner == 0 && meta.mode() & 0o7777 == 0o1777));
+        match trusted {
+            true => Ok(()),
+            false => Err(format!(
+                "{} must be a directory owned by the current user, or owned by root with mode 1777",
+                dir.display()
+            )),
+        }
     }
 
-    fn cmd_thread(path: &String, shell: &Shell) -> Result<(), String> {
-        let server = UnixListener::bind(path).map_err(|err| format!("bind err: {:?}", err))?;
-        while let Ok(conn) = server.incoming().next().ok_or("listen err")? {
-            spawn({
-                let conn_copy = conn
-                    .try_clone()
-                    .map_err(|err| format!("clone err: {:?}", err))?;
-                let shell_copy = shell.clone();
-                move || {
-                    if let Err(err) = Server::handle_cmd_connect(conn_copy, shell_copy)
-                        .map_err(|err| format!("handle cmd connect err: {:?}", err))
-                    {
-                        println!("handle cmd connect err: {}", err);
-                    }
+    /// 锁定套接字路径对应的锁文件，并删除之前的进程遗留的套接字文件。
+    ///
+    /// 进程退出（包括崩溃和被杀死）时锁自动释放，因此能够取得锁说明已有的套接字文件已经没有进程使用，
+    /// 可以安全地替换。套接字正在被其他进程使用时返回错误。
+    ///
+    /// 套接字目录可能是所有用户共享的，锁文件不跟随符号链接，且必须由当前用户所有。
+    fn lock(uds_path: &Path) -> Result<File, String> {
+        let lock_path = lock_path(uds_path);
+        // 锁定后锁文件可能已经被删除或替换，此时锁定的文件不再对应该路径，重新打开
+        let lock = loop {
+            let lock = Server::lock_file(uds_path, &lock_path)?;
+            let locked = lock
+                .metadata()
+                .map_err(|err| format!("stat {} failed: {}", lock_path.display(), err))?;
+            match std::fs::symlink_metadata(&lock_path) {
+                Ok(meta) if (meta.dev(), meta.ino()) == (locked.dev(), locked.ino()) => break lock,
+                Ok(_) => continue,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(format!("stat {} failed: {}", lock_path.display(), err)),
+            }
+        };
+        Server::remove_stale_socket(uds_path)?;
+        Ok(lock)
+    }
+
+    /// 打开并锁定锁文件
+    fn lock_file(uds_path: &Path, lock_path: &Path) -> Result<File, String> {
+        let mut lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .mode(0o644)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(lock_path)
+            .map_err(|err| format!("open {} failed: {}", lock_path.display(), err))?;
+        let meta = lock
+            .metadata()
+            .map_err(|err| format!("stat {} failed: {}", lock_path.display(), err))?;
+        if !meta.is_file() || meta.uid() != unsafe { libc::geteuid() } {
+            return Err(format!(
+                "{} is not a regular file owned by the current user",
+                lock_path.display()
+            ));
+        }
+        // 客户端检查实例是否存活时会短暂持有锁，稍后重试，避免误判为被占用
+        let mut retries = 0;
+        loop {
+            match lock.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if retries < LOCK_RETRIES => {
+                    retries += 1;
+                    sleep(LOCK_RETRY_INTERVAL);
                 }
-            });
+                Err(TryLockError::WouldBlock) => {
+                    return Err(format!(
+                        "{} is in use by another process",
+                        uds_path.display()
+                    ))
+                }
+                Err(TryLockError::Error(err)) => {
+                    return Err(format!("lock {} failed: {}", lock_path.display(), err))
+                }
+            }
         }
+        // 锁文件中记录持有锁的进程 id，便于排查
+        let _ = lock
+            .set_len(0)
+            .and_then(|_| writeln!(lock, "{}", std::process::id()));
+        Ok(lock)
+    }
 
+    /// 删除之前的进程遗留的套接字文件，调用时必须持有锁。
+    fn remove_stale_socket(uds_path: &Path) -> Result<(), String> {
+        match std::fs::symlink_metadata(uds_path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("stat {} failed: {}", uds_path.display(), err)),
+            Ok(meta) if !meta.file_type().is_socket() => {
+                return Err(format!("{} exists and is not a socket", uds_path.display()))
+            }
+            Ok(_) => {
+                // 不使用锁文件的旧版本进程可能仍在侦听该套接字
+                if UnixStream::connect(uds_path).is_ok() {
+                    return Err(format!(
+                        "{} is in use by another process",
+                        uds_path.display()
+                    ));
+                }
+                std::fs::remove_file(uds_path).map_err(|err| {
+                    format!("remove stale socket {} failed: {}", uds_path.display(), err)
+                })?;
+            }
+        }
         Ok(())
     }
 
-    fn redirect_stdout_to_unix_stream(stream: &UnixStream) -> c_int {
-        let original_fd = unsafe { dup(STDOUT_FILENO) }; // 保存原始stdout的文件描述符
-        let ret = original_fd;
-
-        let stream_fd = stream.as_raw_fd(); // 获取UnixStream的文件描述符
-        unsafe { dup2(stream_fd, STDOUT_FILENO) }; // 将stdout的文件描述符重定向到UnixStream
-
-        ret
+    /// 处理以 `.` 开头的会话指令，目前支持：
+    ///
+    /// - `.format text|json`: 设置返回值的显示格式。
+    /// - `.banner on|off`: 设置执行命令时是否输出开始和结束标记。
+    fn handle_directive(line: &str, session: &mut Session) -> Result<(), String> {
+        match split_command(line).ok_or("split directive failed")? {
+            (directive, arg) if directive == ".format" => {
+                session.format = ReplyFormat::from_name(arg.trim())
+                    .ok_or(format!("unknown format: {}", arg.trim()))?;
+                Ok(())
+            }
+            (directive, arg) if directive == ".banner" => {
+                session.banner = match arg.trim() {
+                    "on" => true,
+                    "off" => false,
+                    other => return Err(format!("unknown banner mode: {}", other)),
+                };
+                Ok(())
+            }
+            (directive, _) => Err(format!("unknown directive: {}", directive)),
+        }
     }
 
-    fn restore_stdout(old: c_int) {
-        unsafe { dup2(old, STDOUT_FILENO) }; // 恢复原始stdout的文件描述符
-        unsafe { close(old) }; // 关闭原始文件描述符
+    /// 执行一条命令，命令的输出发送给当前会话的客户端。
+    fn run_command(
+        shell: &Shell,
+        id: u64,
+        line: &str,
+        session: &Session,
+    ) -> Result<Option<String>, String> {
+        let writer = |stream| -> Result<Box<dyn Write>, String> {
+            Ok(Box::new(OutputWriter {
+                conn: session.conn.try_clone().map_err(|err| err.to_string())?,
+                id,
+                stream,
+            }))
+        };
+        let _guard = redirect_output(writer(Stream::Stdout)?, writer(Stream::Stderr)?);
+        shell.execute(line, session.format, session.banner)
     }
 
-    fn output_thread(path: &String) -> Result<(), String> {
-        let mut future: Option<JoinHandle<()>> = None;
-        let mut old_conn: Option<UnixStream> = None;
-        let server = UnixListener::bind(path).map_err(|err| format!("bind err: {:?}", err))?;
-        while let Ok(conn) = server.incoming().next().ok_or("listen err")? {
-            if let Some(o) = old_conn.take() {
-                drop(o);
-                future.take().unwrap().join().unwrap();
-            }
-
-            old_conn = Some(conn.try_clone().map_err(|err| err.to_string())?);
-
-            let mut conn_copy = conn.try_clone().map_err(|err| err.to_string())?;
+    /// 处理客户端发来的一条消息，返回需要回复的消息。
+    fn handle_message(message: Message, shell: &Shell, session: &mut Session) -> Option<Message> {
+        let (id, line) = match message {
+            Message::Invoke { id, line } => (id, line),
+            Message::Ping => return Some(Message::Ping),
+            // 命令在当前线程上顺序执行，收到取消消息时没有正在执行的命令
+            Message::Cancel { .. } => return None,
+            other => return Some(Message::error(format!("unexpected message: {:?}", other))),
+        };
+        let start = Instant::now();
+        let ret = if line.starts_with('.') {
+            Server::handle_directive(&line, session).map(|_| None)
+        } else {
+            Server::run_command(shell, id, &line, session)
+        };
+        // 每条命令执行完后回复执行结果，出错时将错误信息发送给发起命令的客户端
+        let duration = start.elapsed();
+        Some(match ret {
+            Ok(value) => Message::Result {
+                id,
+                value: value.unwrap_or_default(),
+                duration,
+            },
+            Err(message) => Message::Error {
+                id,
+                message,
+                duration,
+            },
+        })
+    }
 
-            let old_stdout = Server::redirect_stdout_to_unix_stream(&conn);
+    /// 与客户端交换握手信息，协议版本不兼容时通知客户端并返回错误，成功时返回客户端的握手信息。
+    fn handshake(
+        conn: &mut UnixStream,
+        reader: &mut MessageReader<impl Read>,
+    ) -> Result<Hello, String> {
+        let hello = Hello::new(
+            concat!("shell_server ", env!("CARGO_PKG_VERSION")),
+            &[
+                Capability::Json,
+                Capability::Streaming,
+                Capability::JsonRpc,
+                Capability::Banner,
+            ],
+        );
+        write_message(conn, &Message::Hello(hello.clone()))?;
+        let ret = match reader.read()? {
+            Message::Hello(peer) => hello.check(&peer).map(|_| peer),
+            other => Err(format!("expect hello, got {:?}", other)),
+        };
+        if let Err(err) = &ret {
+            write_message(conn, &Message::error(err.clone()))?;
+        }
+        ret
+    }
 
-            future = Some(spawn(move || {
-                let mut buf = String::new();
-                let _ = conn_copy
-                    .read_to_string(&mut buf)
-                    .map_err(|err| err.to_string());
-                Server::restore_stdout(old_stdout);
-            }));
+    fn handle_connect(conn: UnixStream, shell: Shell) -> Result<(), String> {
+        let mut reader = BufReader::new(conn.try_clone().map_err(|err| err.to_string())?);
+        // 消息帧以长度开头，长度不超过 MAX_FRAME_SIZE，第一个字节不会是 `{` 或 `[`
+        match reader.fill_buf().map_err(|err| err.to_string())?.first() {
+            // 客户端连接后没有发送任何数据就断开
+            None => return Ok(()),
+            Some(b'{' | b'[') => return jsonrpc::serve(conn, reader, &shell),
+            Some(_) => {}
         }
 
+        let mut reader = MessageReader::new(reader);
+        let mut session = Session {
+            conn,
+            format: ReplyFormat::default(),
+            banner: true,
+        };
+        let peer = Server::handshake(&mut session.conn, &mut reader)?;
+        if peer.supports(Capability::JsonRpc) {
+            // 握手后切换为 JSON-RPC，握手信息之后已经收到的数据属于第一个请求
+            let (reader, received) = reader.into_parts();
+            return jsonrpc::serve(session.conn, Cursor::new(received).chain(reader), &shell);
+        }
+        write_message(
+            &mut session.conn,
+            &Message::CommandList(shell.get_command_metas()),
+        )?;
+        // 客户端断开连接是会话的正常结束
+        while let Some(message) = reader.try_read()? {
+            if let Some(reply) = Server::handle_message(message, &shell, &mut session) {
+                write_message(&mut session.conn, &reply)?;
+            }
+        }
         Ok(())
     }
 
-    /// 在 Server 实例上运行命令并处理输出。
+    /// 在 Server 实例上侦听客户端连接，每个连接在单独的线程中处理。
     ///
     /// # Returns
     ///
@@ -136,22 +406,45 @@ impl Server {
     ///
     /// # Errors
     ///
-    /// 如果命令线程或输出线程返回错误，则返回包含该错误的 Result。
+    /// 如果套接字路径正在被其他进程使用或侦听失败，则返回包含该错误的 Result。
+    /// 崩溃的进程遗留的套接字文件会被替换。
     pub fn run(&mut self) -> Result<(), String> {
-        let uds_cmd_path = self.uds_cmd_path.clone();
-        let uds_output_path = self.uds_output_path.clone();
-
-        let shell_copy = self.shell.clone();
+        if !is_valid_instance_name(self.config.get_name()) {
+            return Err(format!(
+                "invalid instance name: {:?}",
+                self.config.get_name()
+            ));
+        }
+        let dir = self.config.get_dir();
+        Server::create_dir(dir)?;
+        if let Some(parent) = self.uds_path.parent() {
+            Server::create_dir(parent)?;
+        }
+        DiscoveryRecord::remove_stale(dir);
 
-        let command_thread = spawn(move || Server::cmd_thread(&uds_cmd_path, &shell_copy));
-        let output_thread = spawn(move || Server::output_thread(&uds_output_path));
+        let lock = Server::lock(&self.uds_path)?;
+        let server =
+            UnixListener::bind(&self.uds_path).map_err(|err| format!("bind err: {:?}", err))?;
+        self.lock = Some(lock);
+        let mut record = DiscoveryRecord::new(
+            self.config.get_name(),
+            concat!("shell_server ", env!("CARGO_PKG_VERSION")),
+            self.uds_path.clone(),
+        );
+        record.custom_socket = self.config.get_socket().is_some();
+        self.record = Some(record.write(dir)?);
+        while let Ok(conn) = server.incoming().next().ok_or("listen err")? {
+            spawn({
+                let shell_copy = self.shell.clone();
+                move || {
+                    // 不在宿主进程的标准输出中输出，由宿主进程的日志器决定是否记录
+                    if let Err(err) = Server::handle_connect(conn, shell_copy) {
+                        log::warn!("handle connect err: {}", err);
+                    }
+                }
+            });
+        }
 
-        let _ = command_thread
-            .join()
-            .map_err(|err| format!("run command err: {:?}", err))?;
-        let _ = output_thread
-            .join()
-            .map_err(|err| format!("run output err: {:?}", err))?;
         Ok(())
     }
 }
@@ -1,16 +1,120 @@
 use shell_core::*;
-use std::{collections::HashMap, panic, vec};
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use crate::{
+    command::{meta_of, BoundArgument, CommandError, HandlerCommand, IntoCommand, ShellCommand},
+    output::install_panic_hook,
+    registry::SHELL_COMMANDS,
+    reply::{Reply, ReplyFormat},
+};
+
+/// 内置的帮助命令
+pub(crate) const HELP_COMMAND: &str = "help";
+
+/// 已注册的命令及其元信息
+#[derive(Clone)]
+struct Command {
+    func: Arc<dyn ShellCommand>,
+    meta: CommandMeta,
+}
+
+/// 一个参数的名称（按位置传递时为 `None`）和参数值，一个参数可以有多个值，如 `Vec<T>` 类型的参数。
+pub(crate) type NamedValues = (Option<String>, Vec<Argument>);
+
+/// 将参数按名称绑定到命令的参数列表上，省略的参数使用默认值，没有默认值的可选参数为 `None`。
+///
+/// 按位置传递的参数必须在命名参数之前，多余的按位置传递的参数追加到最后一个参数上，由命令自行处理。
+pub(crate) fn bind_arguments(
+    meta: &CommandMeta,
+    args: impl IntoIterator<Item = NamedValues>,
+) -> Result<Vec<BoundArgument>, String> {
+    let mut slots: Vec<Option<Vec<Argument>>> = vec![None; meta.params.len()];
+    let mut extra = vec![];
+    let mut position = 0;
+    let mut named = false;
+    for (name, values) in args {
+        match name {
+            None if named => {
+                return Err(format!(
+                    "positional argument {} can not follow named arguments, \
+                     use a list such as `name=[1,2]` to give a parameter multiple values",
+                    values
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                ))
+            }
+            None if position < slots.len() => {
+                slots[position] = Some(values);
+                position += 1;
+            }
+            None => extra.extend(values),
+            Some(name) => {
+                named = true;
+                let index = meta
+                    .params
+                    .iter()
+                    .position(|p| p.name == name)
+                    .ok_or_else(|| match meta.params.is_empty() {
+                        true => format!(
+                            "unknown parameter `{}`, {} has no parameters",
+                            name, meta.name
+                        ),
+                        false => format!(
+                            "unknown parameter `{}`, expect one of: {}",
+                            name,
+                            meta.params
+                                .iter()
+                                .map(|p| p.name.as_str())
+                                .collect::<Vec<&str>>()
+                                .join(", ")
+                        ),
+                    })?;
+                if slots[index].is_some() {
+                    return Err(format!("parameter `{}` is given more than once", name));
+                }
+                slots[index] = Some(values);
+            }
+        }
+    }
+
+    for (slot, param) in slots.iter_mut().zip(&meta.params) {
+        if let (None, Some(default)) = (&slot, &param.default) {
+            *slot = Some(parse_arguments(default));
+        }
+    }
+
+    for (slot, param) in slots.iter().zip(&meta.params) {
+        if slot.is_none() && !param.optional {
+            return Err(format!("missing parameter `{}`: {}", param.name, param.ty));
+        }
+    }
+
+    if !extra.is_empty() {
+        // 有多余的参数时所有参数都已按位置给出
+        match slots.last_mut() {
+            Some(Some(values)) => values.extend(extra),
+            _ => slots.push(Some(extra)),
+        }
+    }
+    Ok(slots)
+}
 
 #[derive(Clone)]
 pub struct Shell {
-    func_map: HashMap<String, u64>,
+    func_map: HashMap<String, Command>,
 }
 
 #[macro_export]
 macro_rules! reg_shell_cmd {
     ($var:expr,$({$name:expr, $func:expr}),+) => {
         $(
-            $var.reg_func($name.to_string(), unsafe{ std::mem::transmute($func as u64) });
+            $var.reg_func($name.to_string(), $func);
         )+
     };
 }
@@ -41,6 +145,37 @@ impl Shell {
         }
     }
 
+    /// 创建一个 Shell 实例，并注册所有通过 `#[shell_command]` 标注的命令。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use shell_server::{shell_command, Shell};
+    ///
+    /// /// 两数相加
+    /// #[shell_command(name = "add_two")]
+    /// fn add(a: i64, b: i64) -> i64 {
+    ///     a + b
+    /// }
+    ///
+    /// let shell = Shell::with_registered_commands();
+    /// assert_eq!(shell.get_command_meta("add_two").unwrap().help, "两数相加");
+    /// ```
+    pub fn with_registered_commands() -> Shell {
+        let mut shell = Shell::new();
+        for entry in SHELL_COMMANDS {
+            let meta = (entry.register)(&mut shell);
+            meta.help(entry.help)
+                .params(entry.params)
+                .defaults(entry.defaults)
+                .ret(entry.ret);
+            for example in entry.examples {
+                meta.example(example);
+            }
+        }
+        shell
+    }
+
     /// 获取 shell 环境中已注册的命令列表。
     ///
     /// # 返回值
@@ -50,127 +185,371 @@ impl Shell {
         self.func_map.keys().map(|k| k.to_string()).collect()
     }
 
+    /// 获取所有命令的元信息，包括内置的 `help` 命令，按名称排序。
+    pub fn get_command_metas(&self) -> Vec<CommandMeta> {
+        let mut metas: Vec<CommandMeta> = self.func_map.values().map(|c| c.meta.clone()).collect();
+        if !self.func_map.contains_key(HELP_COMMAND) {
+            metas.push(Shell::help_meta());
+        }
+        metas.sort_by(|a, b| a.name.cmp(&b.name));
+        metas
+    }
+
     /// 向 shell 环境中注册一个函数。
     ///
+    /// 除了普通函数，也可以注册捕获了应用状态的闭包，闭包需满足 `Fn + Send + Sync + 'static`。
+    ///
     /// # 参数
     ///
     /// - `name`: 要注册的函数的名称。
-    /// - `addr`: 要注册的函数的地址。
+    /// - `func`: 要注册的函数或闭包，参数需实现 [`FromArgument`](crate::FromArgument)，
+    ///   返回值需实现 [`ToReply`](crate::ToReply)。
     ///
-    pub fn reg_func(&mut self, name: String, addr: u64) {
-        self.func_map.insert(name, addr);
+    /// # 返回值
+    ///
+    /// 命令的元信息，可以继续设置帮助信息和参数名称。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use std::sync::{
+    ///     atomic::{AtomicI64, Ordering},
+    ///     Arc,
+    /// };
+    /// use shell_server::{ReplyFormat, Shell};
+    ///
+    /// struct AppState {
+    ///     counter: AtomicI64,
+    /// }
+    ///
+    /// let state = Arc::new(AppState {
+    ///     counter: AtomicI64::new(0),
+    /// });
+    ///
+    /// let mut shell = Shell::new();
+    /// shell
+    ///     .reg_func("incr".to_string(), {
+    ///         let state = state.clone();
+    ///         move |n: i64| state.counter.fetch_add(n, Ordering::SeqCst) + n
+    ///     })
+    ///     .help("增加计数器")
+    ///     .param_names(&["n"]);
+    ///
+    /// shell.run_command("incr 2", ReplyFormat::Text).unwrap();
+    /// assert_eq!(state.counter.load(Ordering::SeqCst), 2);
+    /// ```
+    pub fn reg_func<M>(&mut self, name: String, func: impl IntoCommand<M>) -> &mut CommandMeta {
+        self.reg_command(name, func.into_command())
     }
 
-    /// 运行 shell 环境中的命令。
+    /// 注册一个直接处理原始参数的命令，适用于参数个数不固定或需要自行解析参数的场景。
     ///
     /// # 参数
     ///
-    /// - `command_line`: 要运行的命令行。
+    /// - `name`: 命令名称。
+    /// - `handler`: 处理函数，接收解析后的参数列表。
+    pub fn reg_handler<F>(&mut self, name: String, handler: F) -> &mut CommandMeta
+    where
+        F: Fn(Vec<Argument>) -> Result<Reply, String> + Send + Sync + 'static,
+    {
+        self.reg_command(name, Arc::new(HandlerCommand { func: handler }))
+    }
+
+    /// 注册一个实现了 [`ShellCommand`] 的命令对象，适用于需要自行保存状态的命令。
+    ///
+    /// # 参数
+    ///
+    /// - `name`: 命令名称。
+    /// - `func`: 命令对象。
+    pub fn reg_command(&mut self, name: String, func: Arc<dyn ShellCommand>) -> &mut CommandMeta {
+        let meta = meta_of(&name, func.as_ref());
+        &mut self
+            .func_map
+            .entry(name)
+            .insert_entry(Command { func, meta })
+            .into_mut()
+            .meta
+    }
+
+    /// 注销一个命令。
     ///
     /// # 返回值
     ///
-    /// 运行命令的结果。
-    pub fn run_command(&self, command_line: &String) -> Result<(), String> {
-        panic::catch_unwind(|| {
-            let (command, arguments) =
-                split_command(command_line.trim()).ok_or("split command failed")?;
+    /// 命令存在时返回 `true`。
+    pub fn unreg_func(&mut self, name: &str) -> bool {
+        self.func_map.remove(name).is_some()
+    }
 
-            let addr = self
-                .func_map
-                .get(&command)
-                .ok_or(format!("{} not found", command))?;
+    /// 获取命令的元信息。
+    pub fn get_command_meta(&self, name: &str) -> Option<&CommandMeta> {
+        self.func_map.get(name).map(|c| &c.meta)
+    }
+
+    /// 内置 `help` 命令的元信息
+    fn help_meta() -> CommandMeta {
+        let mut meta = CommandMeta {
+            name: HELP_COMMAND.to_owned(),
+            ..Default::default()
+        };
+        meta.help("show all commands, or the detail of one command or namespace")
+            .params(&[("command", "Option<String>")])
+            .optional(&["command"])
+            .example("help")
+            .example("help add_two")
+            .example("help db.pool");
+        meta
+    }
+
+    /// 命令是否存在，包括内置的 `help` 命令。
+    fn has_command(&self, name: &str) -> bool {
+        name == HELP_COMMAND || self.func_map.contains_key(name)
+    }
 
-            let mut argument_int64 = vec![];
+    /// 是否存在以 `prefix.` 开头的命令，即 `prefix` 是否为一个命名空间。
+    fn is_namespace(&self, prefix: &str) -> bool {
+        let prefix = format!("{}.", prefix);
+        self.func_map.keys().any(|k| k.starts_with(&prefix))
+    }
+
+    /// 将另一个 shell 中的所有命令以 `prefix.` 为前缀挂载到当前 shell 中。
+    ///
+    /// # 示例
+    ///
+    /// ```rust
+    /// use shell_server::{ReplyFormat, Shell};
+    ///
+    /// let mut pool = Shell::new();
+    /// pool.reg_func("stats".to_string(), || 42);
+    ///
+    /// let mut shell = Shell::new();
+    /// shell.mount("db.pool", pool);
+    ///
+    /// assert!(shell.get_command_meta("db.pool.stats").is_some());
+    /// shell.run_command("db pool stats", ReplyFormat::Text).unwrap();
+    /// ```
+    pub fn mount(&mut self, prefix: &str, shell: Shell) {
+        for (name, mut command) in shell.func_map {
+            let name = format!("{}.{}", prefix, name);
+            command.meta.name = name.clone();
+            self.func_map.insert(name, command);
+        }
+    }
 
-            let mut str_args = vec![String::from(""); 10]; // 这个变量不能删除,需要这个vec保持对象的生命周期
-            let mut index = 0;
+    /// 解析命令行中的命令名称和参数。
+    ///
+    /// 命名空间中的命令既可以写作 `db.pool.stats`，也可以写作 `db pool stats`，
+    /// 后者会尽可能匹配最长的命令名称，剩余部分作为参数。
+    fn resolve_command(&self, command_line: &str) -> Result<(String, String), String> {
+        let (mut path, mut rest) =
+            split_command(command_line.trim()).ok_or("split command failed")?;
+        let mut found = None;
+        if self.has_command(&path) {
+            found = Some((path.clone(), rest.clone()));
+        }
+        while self.is_namespace(&path) {
+            let (word, remain) = match split_command(rest.trim_start()) {
+                Some((word, remain)) if !word.is_empty() => (word, remain),
+                _ => break,
+            };
+            path = format!("{}.{}", path, word);
+            rest = remain;
+            if self.has_command(&path) {
+                found = Some((path.clone(), rest.clone()));
+            }
+        }
+        match found {
+            Some(found) => Ok(found),
+            None if self.is_namespace(&path) => Err(format!(
+                "{} is a namespace, use `help {}` to list its commands",
+                path, path
+            )),
+            None => Err(format!("{} not found", path)),
+        }
+    }
 
-            for a in parse_arguments(arguments.as_str()) {
-                match a {
-                    Argument::Str(s) => {
-                        str_args[index] = s.clone();
-                        argument_int64.push(unsafe { std::mem::transmute(&str_args[index]) });
-                        index += 1;
+    /// 列出命名空间中的命令，子命名空间折叠为一行，`namespace` 为空时列出顶层命令。
+    fn group_help(&self, namespace: &str) -> String {
+        let prefix = match namespace {
+            "" => String::new(),
+            ns => format!("{}.", ns),
+        };
+        let mut entries: Vec<(String, String)> = vec![];
+        let mut groups: Vec<(String, usize)> = vec![];
+        for meta in self.get_command_metas() {
+            let rest = match meta.name.strip_prefix(&prefix) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            match rest.split_once('.') {
+                Some((group, _)) => {
+                    let group = format!("{}{}.*", prefix, group);
+                    match groups.iter_mut().find(|(g, _)| *g == group) {
+                        Some((_, count)) => *count += 1,
+                        None => groups.push((group, 1)),
                     }
-                    Argument::Int(i) => argument_int64.push(i as u64),
                 }
+                None => entries.push((meta.signature(), meta.summary().to_owned())),
             }
+        }
+        entries.extend(
+            groups
+                .into_iter()
+                .map(|(group, count)| (group, format!("{} commands", count))),
+        );
+
+        let width = entries
+            .iter()
+            .map(|(left, _)| left.chars().count())
+            .max()
+            .unwrap_or_default();
+        entries
+            .iter()
+            .map(|(left, right)| {
+                format!("  {:width$}  {}", left, right, width = width)
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// 生成帮助信息，不指定命令时列出顶层命令，指定命名空间时列出该命名空间中的命令。
+    fn help(&self, topic: &str) -> Result<String, String> {
+        let topic = topic.split_whitespace().collect::<Vec<&str>>().join(".");
+        if topic.is_empty() {
+            return Ok(self.group_help(""));
+        }
 
-            macro_rules! call_func {
-                ($func:expr) => {
-                    Ok($func(*addr)())
-                };
-                ($func:expr,$($n:expr),*) => {
-                    Ok(
-                        $func(*addr)(
-                            $(argument_int64[$n],)+
-                        )
-                    )
-                };
+        let meta = match self.func_map.get(&topic) {
+            Some(c) => c.meta.clone(),
+            None if topic == HELP_COMMAND => Shell::help_meta(),
+            None if self.is_namespace(&topic) => return Ok(self.group_help(&topic)),
+            None => return Err(format!("{} not found", topic)),
+        };
+        let mut lines = vec![meta.signature()];
+        if !meta.help.is_empty() {
+            lines.push(String::new());
+            lines.extend(meta.help.lines().map(|l| format!("  {}", l)));
+        }
+        if !meta.params.is_empty() {
+            lines.push(String::new());
+            lines.push("Parameters:".to_owned());
+            lines.extend(meta.params.iter().map(|p| format!("  {}", p)));
+        }
+        if !meta.ret.is_empty() && meta.ret != "()" {
+            lines.push(String::new());
+            lines.push(format!("Returns: {}", meta.ret));
+        }
+        if !meta.examples.is_empty() {
+            lines.push(String::new());
+            lines.push("Examples:".to_owned());
+            lines.extend(meta.examples.iter().map(|e| format!("  {}", e)));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// 运行 shell 环境中的命令。
+    ///
+    /// # 参数
+    ///
+    /// - `command_line`: 要运行的命令行。
+    /// - `format`: 返回值的显示格式。
+    ///
+    /// # 返回值
+    ///
+    /// 按 `format` 格式化后的返回值，命令没有返回值时为 `None`。
+    pub fn run_command(
+        &self,
+        command_line: &str,
+        format: ReplyFormat,
+    ) -> Result<Option<String>, String> {
+        self.execute(command_line, format, true)
+    }
+
+    /// 运行 shell 环境中的命令，`banner` 为 `false` 时不输出开始和结束标记。
+    pub(crate) fn execute(
+        &self,
+        command_line: &str,
+        format: ReplyFormat,
+        banner: bool,
+    ) -> Result<Option<String>, String> {
+        catch_panic(|| {
+            let (command, arguments) = self.resolve_command(command_line)?;
+
+            if command == HELP_COMMAND && !self.func_map.contains_key(HELP_COMMAND) {
+                crate::shell_println!("{}", self.help(arguments.trim())?);
+                return Ok(None);
             }
 
-            println!(
+            let Command { func, meta } = self
+                .func_map
+                .get(&command)
+                .ok_or(format!("{} not found", command))?;
+            let arguments = bind_arguments(
+                meta,
+                parse_named_arguments(&arguments)
+                    .into_iter()
+                    .map(|arg| match arg.value {
+                        // 列表中的每个值为参数的一个值
+                        Argument::List(items) => (arg.name, items),
+                        value => (arg.name, vec![value]),
+                    }),
+            )?;
+
+            if !banner {
+                return Ok(func.call_bound(arguments)?.render(format));
+            }
+            crate::shell_println!(
                 "\x1B[34m------------[begin to excel func {}]------------\x1B[0m",
                 command
             );
-            if let Ok(ret) = match argument_int64.len() {
-                0 => call_func!(create_fn_0),
-                1 => call_func!(create_fn_1, 0),
-                2 => call_func!(create_fn_2, 0, 1),
-                3 => call_func!(create_fn_3, 0, 1, 2),
-                4 => call_func!(create_fn_4, 0, 1, 2, 3),
-                5 => call_func!(create_fn_5, 0, 1, 2, 3, 4),
-                6 => call_func!(create_fn_6, 0, 1, 2, 3, 4, 5),
-                7 => call_func!(create_fn_7, 0, 1, 2, 3, 4, 5, 6),
-                8 => call_func!(create_fn_8, 0, 1, 2, 3, 4, 5, 6, 7),
-                9 => call_func!(create_fn_9, 0, 1, 2, 3, 4, 5, 6, 7, 8),
-                10 => call_func!(create_fn_10, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9),
-                _ => Err("too many arguments".to_string()),
-            } {
-                println!(
+            let ret = func.call_bound(arguments)?.render(format);
+            match &ret {
+                Some(ret) if !ret.contains('\n') => crate::shell_println!(
                     "\x1B[35m------------[end to excel func {}]:{}------------\x1B[0m",
-                    command, ret
-                );
+                    command,
+                    ret
+                ),
+                // 多行的返回值单独输出，避免打乱结束标记
+                Some(ret) => crate::shell_println!(
+                    "{}\n\x1B[35m------------[end to excel func {}]------------\x1B[0m",
+                    ret,
+                    command
+                ),
+                None => crate::shell_println!(
+                    "\x1B[35m------------[end to excel func {}]------------\x1B[0m",
+                    command
+                ),
             }
-            Ok(())
+            Ok(ret)
         })
-        .map_err(|err| format!("run command err: {:?}", err))?
     }
-}
 
-macro_rules! def_create_fn {
-    ($name:ident) => {
-        fn $name(addr:u64) -> fn() -> u64 {
-            unsafe { std::mem::transmute(addr) }
-        }
-    };
-    ($name:ident, $($param:ty),*) => {
-        fn $name(addr:u64) -> fn($($param,)+) -> u64 {
-            unsafe { std::mem::transmute(addr) }
-        }
-    };
+    /// 以绑定好的参数调用命令，不输出开始和结束标记，用于 JSON-RPC 等面向程序的调用。
+    pub(crate) fn invoke(
+        &self,
+        name: &str,
+        args: Vec<BoundArgument>,
+    ) -> Result<Reply, CommandError> {
+        let Command { func, .. } = self
+            .func_map
+            .get(name)
+            .ok_or(format!("{} not found", name))?;
+        catch_panic(|| func.call_bound(args))
+    }
 }
 
-def_create_fn!(create_fn_0);
-def_create_fn!(create_fn_1, u64);
-def_create_fn!(create_fn_2, u64, u64);
-def_create_fn!(create_fn_3, u64, u64, u64);
-def_create_fn!(create_fn_4, u64, u64, u64, u64);
-def_create_fn!(create_fn_5, u64, u64, u64, u64, u64);
-def_create_fn!(create_fn_6, u64, u64, u64, u64, u64, u64);
-def_create_fn!(create_fn_7, u64, u64, u64, u64, u64, u64, u64);
-def_create_fn!(create_fn_8, u64, u64, u64, u64, u64, u64, u64, u64);
-def_create_fn!(create_fn_9, u64, u64, u64, u64, u64, u64, u64, u64, u64);
-def_create_fn!(
-    create_fn_10,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64,
-    u64
-);
+/// 执行命令，命令执行过程中发生的 panic 转换为错误信息。
+fn catch_panic<T, E: From<String>>(f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    install_panic_hook();
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|err| {
+        let message = match err.downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            None => match err.downcast_ref::<String>() {
+                Some(s) => s.clone(),
+                None => "unknown panic payload".to_owned(),
+            },
+        };
+        format!("command panicked: {}", message)
+    })?
+}
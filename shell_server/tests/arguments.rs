@@ -0,0 +1,130 @@
+//! 参数绑定：命名参数、默认值、可以省略的参数以及各种错误
+
+use shell_server::{shell_command, ReplyFormat, Shell};
+
+type Weights = Vec<i64>;
+
+#[shell_command]
+fn full_path_option(a: i64, b: std::option::Option<i64>) -> i64 {
+    a + b.unwrap_or(10)
+}
+
+#[shell_command]
+fn alias_vec(a: i64, weights: Weights) -> i64 {
+    a + weights.iter().sum::<i64>()
+}
+
+fn shell() -> Shell {
+    let mut shell = Shell::with_registered_commands();
+    shell
+        .reg_func(
+            "scale".to_string(),
+            |value: f64, ratio: f64, round: bool| {
+                let ret = value * ratio;
+                match round {
+                    true => ret.round(),
+                    false => ret,
+                }
+            },
+        )
+        .param_names(&["value", "ratio", "round"])
+        .defaults(&[("ratio", "1.0"), ("round", "false")]);
+    shell.reg_func("pair".to_string(), |a: i64, b: Option<i64>| {
+        a + b.unwrap_or(0)
+    });
+    shell.reg_func("none".to_string(), || 0);
+    shell
+        .reg_func(
+            "skip".to_string(),
+            |a: i64, b: Option<i64>, c: Option<i64>| format!("{} {:?} {:?}", a, b, c),
+        )
+        .param_names(&["a", "b", "c"]);
+    shell.reg_func("concat".to_string(), |a: String, b: String| a + &b);
+    shell
+}
+
+fn run(line: &str) -> Result<Option<String>, String> {
+    shell().run_command(line, ReplyFormat::Text)
+}
+
+#[test]
+fn optional_from_type_not_from_type_name() {
+    assert_eq!(run("full_path_option 1"), Ok(Some("11".to_owned())));
+    assert_eq!(run("alias_vec 1"), Ok(Some("1".to_owned())));
+    assert_eq!(run("alias_vec 1,2,3"), Ok(Some("6".to_owned())));
+}
+
+#[test]
+fn list_for_named_vec() {
+    assert_eq!(run("alias_vec 1,weights=[2,3]"), Ok(Some("6".to_owned())));
+    assert_eq!(run("alias_vec weights=[],a=1"), Ok(Some("1".to_owned())));
+    assert_eq!(run("alias_vec 1,[2,3]"), Ok(Some("6".to_owned())));
+
+    let err = run("alias_vec a=1,2").unwrap_err();
+    assert!(err.contains("name=[1,2]"), "{}", err);
+}
+
+#[test]
+fn named_and_default_arguments() {
+    assert_eq!(run("scale 3"), Ok(Some("3.0".to_owned())));
+    assert_eq!(
+        run("scale 3,round=true,ratio=0.5"),
+        Ok(Some("2.0".to_owned()))
+    );
+    assert_eq!(run("pair arg1=1"), Ok(Some("1".to_owned())));
+}
+
+#[test]
+fn skip_optional_parameters_by_name() {
+    assert_eq!(run("skip 1,c=2"), Ok(Some("1 None Some(2)".to_owned())));
+    assert_eq!(run("skip c=2,a=1"), Ok(Some("1 None Some(2)".to_owned())));
+    assert_eq!(run("skip 1,2"), Ok(Some("1 Some(2) None".to_owned())));
+    let err = run("skip 1,2,3,4").unwrap_err();
+    assert!(err.contains("too many arguments"), "{}", err);
+}
+
+#[test]
+fn positional_after_named() {
+    let err = run("scale value=3,0.5").unwrap_err();
+    assert!(err.contains("can not follow named arguments"), "{}", err);
+}
+
+#[test]
+fn unknown_parameter() {
+    let err = run("scale 3,rate=2").unwrap_err();
+    assert!(err.contains("unknown parameter `rate`"), "{}", err);
+    assert!(err.contains("value, ratio, round"), "{}", err);
+
+    let err = run("none x=1").unwrap_err();
+    assert!(err.contains("none has no parameters"), "{}", err);
+}
+
+#[test]
+fn parameter_given_twice() {
+    let err = run("scale 3,value=4").unwrap_err();
+    assert!(
+        err.contains("parameter `value` is given more than once"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn missing_parameter() {
+    let err = run("scale ratio=2").unwrap_err();
+    assert!(err.contains("missing parameter `value`"), "{}", err);
+
+    let err = run("pair").unwrap_err();
+    assert!(err.contains("missing parameter `arg1`"), "{}", err);
+
+    // 中间省略的可选参数无法按位置传递
+    let err = run("full_path_option b=1").unwrap_err();
+    assert!(err.contains("missing parameter `a`"), "{}", err);
+}
+
+#[test]
+fn string_keeps_original_text() {
+    assert_eq!(run("concat 1e3,+5"), Ok(Some("1e3+5".to_owned())));
+    assert_eq!(run("concat 0x10,007"), Ok(Some("0x10007".to_owned())));
+    assert_eq!(run("concat 1.10,true"), Ok(Some("1.10true".to_owned())));
+}